@@ -0,0 +1,405 @@
+//! Off-chain helpers for following polls through the program's events
+//! instead of fetching every `CandidateAccount`.
+//!
+//! Feed the log messages of each successful transaction — from
+//! `logsSubscribe`, or `meta.logMessages` of a fetched transaction — to
+//! [`PollTally::apply_logs`] in the order the transactions landed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anchor_lang::{prelude::Pubkey, AnchorDeserialize, Discriminator};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    BallotMode, CandidateRegistered, CandidateRemoved, PollCreated, VoteCast, VoteChanged,
+};
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VotingEvent {
+    PollCreated(PollCreated),
+    CandidateRegistered(CandidateRegistered),
+    CandidateRemoved(CandidateRemoved),
+    VoteCast(VoteCast),
+    VoteChanged(VoteChanged),
+}
+
+impl VotingEvent {
+    /// Decodes the payload of a `Program data:` log line. Returns `None` for
+    /// data that is not one of this program's events.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        fn event<T: AnchorDeserialize + Discriminator>(data: &[u8]) -> Option<T> {
+            let mut payload = data.strip_prefix(T::DISCRIMINATOR)?;
+            T::deserialize(&mut payload).ok()
+        }
+
+        event(data)
+            .map(VotingEvent::PollCreated)
+            .or_else(|| event(data).map(VotingEvent::CandidateRegistered))
+            .or_else(|| event(data).map(VotingEvent::CandidateRemoved))
+            .or_else(|| event(data).map(VotingEvent::VoteCast))
+            .or_else(|| event(data).map(VotingEvent::VoteChanged))
+    }
+
+    pub fn poll_id(&self) -> u64 {
+        match self {
+            VotingEvent::PollCreated(event) => event.poll_id,
+            VotingEvent::CandidateRegistered(event) => event.poll_id,
+            VotingEvent::CandidateRemoved(event) => event.poll_id,
+            VotingEvent::VoteCast(event) => event.poll_id,
+            VotingEvent::VoteChanged(event) => event.poll_id,
+        }
+    }
+}
+
+/// Extracts this program's events from one transaction's log messages.
+/// `Program data:` lines are only decoded while the voting program is the
+/// innermost running program, so events logged by other programs in the same
+/// transaction are ignored.
+pub fn parse_logs<'a>(logs: impl IntoIterator<Item = &'a str>) -> Vec<VotingEvent> {
+    let program_id = crate::ID.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA) {
+            if stack.last() == Some(&program_id.as_str()) {
+                events.extend(
+                    STANDARD
+                        .decode(data)
+                        .ok()
+                        .and_then(|data| VotingEvent::decode(&data)),
+                );
+            }
+            continue;
+        }
+
+        let Some(rest) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        let Some((program, status)) = rest.split_once(' ') else {
+            continue;
+        };
+        if status.starts_with("invoke [") {
+            stack.push(program);
+        } else if status == "success" || status.starts_with("failed") {
+            stack.pop();
+        }
+    }
+
+    events
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateTally {
+    pub name: String,
+    /// Weighted votes, as `candidate_votes` holds them on chain. Stays zero
+    /// in ranked-choice polls, which are counted by `tally_round`.
+    pub votes: u64,
+}
+
+/// A ranked ballot and its weight, kept so an instant-runoff count can be
+/// run off chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankedBallot {
+    pub rankings: Vec<u64>,
+    pub weight: u64,
+}
+
+/// Live tally of a single poll, rebuilt from its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollTally {
+    pub poll_id: u64,
+    /// `None` until the `PollCreated` event has been seen.
+    pub ballot_mode: Option<BallotMode>,
+    /// Candidates keyed by `candidate_index`.
+    pub candidates: BTreeMap<u64, CandidateTally>,
+    pub ranked_ballots: Vec<RankedBallot>,
+    /// Wallets with a counted ballot. Quadratic voters can emit several
+    /// `VoteCast` events but cast one ballot.
+    pub voters: BTreeSet<Pubkey>,
+    pub total_ballots: u64,
+    pub total_votes: u64,
+}
+
+impl PollTally {
+    pub fn new(poll_id: u64) -> Self {
+        Self {
+            poll_id,
+            ballot_mode: None,
+            candidates: BTreeMap::new(),
+            ranked_ballots: Vec::new(),
+            voters: BTreeSet::new(),
+            total_ballots: 0,
+            total_votes: 0,
+        }
+    }
+
+    /// Applies every event for this poll found in one transaction's logs.
+    pub fn apply_logs<'a>(&mut self, logs: impl IntoIterator<Item = &'a str>) {
+        for event in parse_logs(logs) {
+            self.apply(&event);
+        }
+    }
+
+    /// Applies a single event. Events for other polls are ignored.
+    pub fn apply(&mut self, event: &VotingEvent) {
+        if event.poll_id() != self.poll_id {
+            return;
+        }
+
+        match event {
+            VotingEvent::PollCreated(event) => self.ballot_mode = Some(event.ballot_mode),
+            VotingEvent::CandidateRegistered(event) => {
+                self.candidates.insert(
+                    event.candidate_index,
+                    CandidateTally {
+                        name: event.candidate_name.clone(),
+                        votes: 0,
+                    },
+                );
+            }
+            VotingEvent::CandidateRemoved(event) => {
+                self.candidates.remove(&event.candidate_index);
+            }
+            VotingEvent::VoteCast(event) => {
+                if self.voters.insert(event.voter) {
+                    self.total_ballots += 1;
+                }
+                self.total_votes += event.weight;
+
+                if self.ballot_mode == Some(BallotMode::RankedChoice) {
+                    self.ranked_ballots.push(RankedBallot {
+                        rankings: event.choices.clone(),
+                        weight: event.weight,
+                    });
+                    return;
+                }
+
+                for index in event.choices.iter() {
+                    if let Some(candidate) = self.candidates.get_mut(index) {
+                        candidate.votes += event.weight;
+                    }
+                }
+            }
+            VotingEvent::VoteChanged(event) => {
+                if let Some(candidate) = self.candidates.get_mut(&event.previous_candidate_index) {
+                    // A tally started part way through a poll may not have
+                    // seen the vote being moved.
+                    candidate.votes = candidate.votes.saturating_sub(event.weight);
+                }
+                if let Some(candidate) = self.candidates.get_mut(&event.candidate_index) {
+                    candidate.votes += event.weight;
+                }
+            }
+        }
+    }
+
+    /// Candidates with the most votes so far; more than one on a tie, none
+    /// before the first vote.
+    pub fn leaders(&self) -> Vec<&CandidateTally> {
+        let top = self
+            .candidates
+            .values()
+            .map(|candidate| candidate.votes)
+            .max()
+            .unwrap_or(0);
+        self.candidates
+            .values()
+            .filter(|candidate| top > 0 && candidate.votes == top)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+
+    const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+    const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGhPfSWb6ZkeQpmAQEr9ELdsn";
+
+    fn data_line(event: &impl Event) -> String {
+        format!("{PROGRAM_DATA}{}", STANDARD.encode(event.data()))
+    }
+
+    fn vote_cast(poll_id: u64, voter: Pubkey, candidate_index: u64, weight: u64) -> VoteCast {
+        VoteCast {
+            poll_id,
+            voter,
+            choices: vec![candidate_index],
+            weight,
+        }
+    }
+
+    /// Logs of a `vote` call, as the runtime prints them.
+    fn vote_logs(event: &VoteCast) -> Vec<String> {
+        let program = crate::ID.to_string();
+        vec![
+            format!("Program {program} invoke [1]"),
+            "Program log: Instruction: Vote".to_string(),
+            format!("Program {SYSTEM_PROGRAM} invoke [2]"),
+            format!("Program {SYSTEM_PROGRAM} success"),
+            data_line(event),
+            format!("Program {program} consumed 24170 of 200000 compute units"),
+            format!("Program {program} success"),
+        ]
+    }
+
+    fn registered(poll_id: u64, candidate_index: u64, name: &str) -> VotingEvent {
+        VotingEvent::CandidateRegistered(CandidateRegistered {
+            poll_id,
+            candidate_index,
+            candidate_name: name.to_string(),
+        })
+    }
+
+    fn votes(tally: &PollTally) -> Vec<u64> {
+        tally
+            .candidates
+            .values()
+            .map(|candidate| candidate.votes)
+            .collect()
+    }
+
+    #[test]
+    fn parses_events_logged_after_a_nested_cpi() {
+        let event = vote_cast(1, Pubkey::new_unique(), 0, 1);
+        let logs = vote_logs(&event);
+
+        assert_eq!(
+            parse_logs(logs.iter().map(String::as_str)),
+            vec![VotingEvent::VoteCast(event)]
+        );
+    }
+
+    #[test]
+    fn ignores_program_data_logged_by_other_programs() {
+        let program = crate::ID.to_string();
+        let spoofed = data_line(&vote_cast(1, Pubkey::new_unique(), 0, 1_000));
+        let event = vote_cast(1, Pubkey::new_unique(), 0, 1);
+        let logs = [
+            // A top-level instruction of another program logging a voting event.
+            format!("Program {TOKEN_PROGRAM} invoke [1]"),
+            spoofed.clone(),
+            format!("Program {TOKEN_PROGRAM} success"),
+            // The voting program calling out to a program that does the same.
+            format!("Program {program} invoke [1]"),
+            format!("Program {TOKEN_PROGRAM} invoke [2]"),
+            spoofed,
+            format!("Program {TOKEN_PROGRAM} success"),
+            data_line(&event),
+            format!("Program {program} success"),
+        ];
+
+        assert_eq!(
+            parse_logs(logs.iter().map(String::as_str)),
+            vec![VotingEvent::VoteCast(event)]
+        );
+    }
+
+    #[test]
+    fn pops_programs_that_fail() {
+        let program = crate::ID.to_string();
+        let event = vote_cast(1, Pubkey::new_unique(), 0, 1);
+        let logs = [
+            format!("Program {program} invoke [1]"),
+            format!("Program {TOKEN_PROGRAM} invoke [2]"),
+            "Program log: Error: insufficient funds".to_string(),
+            format!("Program {TOKEN_PROGRAM} failed: custom program error: 0x1"),
+            data_line(&event),
+            format!("Program {program} success"),
+            data_line(&event),
+        ];
+
+        assert_eq!(
+            parse_logs(logs.iter().map(String::as_str)),
+            vec![VotingEvent::VoteCast(event)]
+        );
+    }
+
+    #[test]
+    fn skips_data_that_is_not_a_voting_event() {
+        let program = crate::ID.to_string();
+        let logs = [
+            format!("Program {program} invoke [1]"),
+            format!("{PROGRAM_DATA}{}", STANDARD.encode([0u8; 16])),
+            format!("{PROGRAM_DATA}not base64"),
+            format!("Program {program} success"),
+        ];
+
+        assert!(parse_logs(logs.iter().map(String::as_str)).is_empty());
+    }
+
+    #[test]
+    fn tallies_votes_and_vote_changes() {
+        let voter = Pubkey::new_unique();
+        let mut tally = PollTally::new(1);
+        tally.apply(&registered(1, 0, "Alice"));
+        tally.apply(&registered(1, 1, "Bob"));
+        tally.apply(&registered(2, 0, "Other poll"));
+
+        let logs = vote_logs(&vote_cast(1, voter, 0, 3));
+        tally.apply_logs(logs.iter().map(String::as_str));
+        let changed = VoteChanged {
+            poll_id: 1,
+            voter,
+            previous_candidate_index: 0,
+            candidate_index: 1,
+            weight: 3,
+        };
+        tally.apply_logs(
+            [
+                format!("Program {} invoke [1]", crate::ID),
+                data_line(&changed),
+                format!("Program {} success", crate::ID),
+            ]
+            .iter()
+            .map(String::as_str),
+        );
+
+        assert_eq!(votes(&tally), vec![0, 3]);
+        assert_eq!(tally.total_ballots, 1);
+        assert_eq!(tally.total_votes, 3);
+        assert_eq!(tally.leaders()[0].name, "Bob");
+    }
+
+    #[test]
+    fn vote_change_for_an_unseen_vote_does_not_underflow() {
+        let mut tally = PollTally::new(1);
+        tally.apply(&registered(1, 0, "Alice"));
+        tally.apply(&registered(1, 1, "Bob"));
+
+        tally.apply(&VotingEvent::VoteChanged(VoteChanged {
+            poll_id: 1,
+            voter: Pubkey::new_unique(),
+            previous_candidate_index: 0,
+            candidate_index: 1,
+            weight: 2,
+        }));
+
+        assert_eq!(votes(&tally), vec![0, 2]);
+    }
+
+    #[test]
+    fn counts_a_quadratic_voter_once() {
+        let voter = Pubkey::new_unique();
+        let mut tally = PollTally::new(1);
+        tally.apply(&VotingEvent::PollCreated(PollCreated {
+            poll_id: 1,
+            authority: Pubkey::new_unique(),
+            ballot_mode: BallotMode::Quadratic,
+            start_time: 0,
+            end_time: 1,
+        }));
+        tally.apply(&registered(1, 0, "Alice"));
+
+        tally.apply(&VotingEvent::VoteCast(vote_cast(1, voter, 0, 2)));
+        tally.apply(&VotingEvent::VoteCast(vote_cast(1, voter, 0, 1)));
+
+        assert_eq!(votes(&tally), vec![3]);
+        assert_eq!(tally.total_ballots, 1);
+        assert_eq!(tally.total_votes, 3);
+    }
+}
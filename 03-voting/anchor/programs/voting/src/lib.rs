@@ -1,100 +1,2315 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::system_program::{self, Allocate, Assign, CreateAccount, Transfer};
+use anchor_spl::associated_token::{
+    create_idempotent, get_associated_token_address_with_program_id, AssociatedToken, Create,
+};
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+use solana_instructions_sysvar::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+};
+use solana_sha256_hasher::hashv;
+
+#[cfg(feature = "client")]
+pub mod client;
 
 declare_id!("65KHV8cXwJ8apTKMqnpSdhdHkHhRySatgKMwnxm6C3gG");
 
-#[program]
-pub mod voting {
-    use super::*;
+/// Ranked-choice polls track eliminated candidates in a `u32` bitmask.
+pub const MAX_RANKED_CANDIDATES: usize = 32;
+
+/// Most candidates a single approval ballot can select.
+pub const MAX_CHOICES: usize = 32;
+
+/// Scope seed of a delegation that applies to every poll.
+pub const GLOBAL_DELEGATION: u64 = u64::MAX;
+
+/// Most mints an NFT-gated poll can accept, stored at 32 bytes each in
+/// `PollAccount`. Covers the 11 mints of the Labubu collection with room to
+/// spare. It does not bound a single `vote_with_nft` call: each mint used
+/// there takes four accounts, so one legacy transaction fits about six, and
+/// voters with more gate mints vote in several calls.
+pub const MAX_GATE_MINTS: usize = 16;
+
+/// Native program that verifies the ed25519 signatures of relayed ballots.
+pub const ED25519_PROGRAM_ID: Pubkey = pubkey!("Ed25519SigVerify111111111111111111111111111");
+
+#[program]
+pub mod voting {
+    use super::*;
+
+    pub fn initialize_poll(
+        ctx: Context<InitializePoll>,
+        poll_id: u64,
+        start_time: u64,
+        end_time: u64,
+        name: String,
+        description: String,
+        settings: PollSettings,
+    ) -> Result<()> {
+        let PollSettings {
+            ballot_mode,
+            reveal_end,
+            eligibility_root,
+            quorum,
+            pass_threshold_bps,
+            max_choices,
+            registration_start,
+            credit_budget,
+            gate_mints,
+        } = settings;
+
+        if start_time >= end_time
+            || reveal_end.is_some_and(|reveal_end| reveal_end <= end_time)
+            || registration_start.is_some_and(|registration_start| registration_start >= start_time)
+        {
+            return Err(ErrorCode::InvalidVotingWindow.into());
+        }
+
+        if max_choices == 0 || max_choices as usize > MAX_CHOICES {
+            return Err(ErrorCode::InvalidChoices.into());
+        }
+
+        if gate_mints.len() > MAX_GATE_MINTS
+            || gate_mints
+                .iter()
+                .enumerate()
+                .any(|(i, mint)| gate_mints[..i].contains(mint))
+        {
+            return Err(ErrorCode::InvalidGateMints.into());
+        }
+
+        // Token weights cannot be combined with ranked ballots, secret ballots,
+        // allowlist weights or multi-select ballots, and secret, ranked and
+        // quadratic ballots are open, unweighted and single-choice. NFT-gated
+        // polls are open single-choice plurality polls weighted by holdings.
+        let weighted = ctx.accounts.governance_mint.is_some();
+        let ranked = ballot_mode == BallotMode::RankedChoice;
+        let quadratic = ballot_mode == BallotMode::Quadratic;
+        let multi_select = max_choices > 1;
+        let gated = !gate_mints.is_empty();
+        if (weighted && (ranked || eligibility_root.is_some() || multi_select))
+            || (reveal_end.is_some() && (ranked || weighted || multi_select))
+            || (ranked && multi_select)
+            || (quadratic && (weighted || reveal_end.is_some() || multi_select))
+            || (gated
+                && (weighted
+                    || ranked
+                    || quadratic
+                    || reveal_end.is_some()
+                    || eligibility_root.is_some()
+                    || multi_select))
+        {
+            return Err(ErrorCode::UnsupportedPollConfig.into());
+        }
+
+        if quadratic != (credit_budget > 0) {
+            return Err(ErrorCode::InvalidCreditBudget.into());
+        }
+
+        // Quorum and pass threshold only apply to single-choice yes/no polls.
+        if pass_threshold_bps > 10_000
+            || (pass_threshold_bps == 0 && quorum > 0)
+            || (pass_threshold_bps > 0 && (ranked || quadratic || multi_select))
+        {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+
+        ctx.accounts.poll_account.authority = ctx.accounts.signer.key();
+        ctx.accounts.poll_account.poll_name = name;
+        ctx.accounts.poll_account.poll_description = description;
+        ctx.accounts.poll_account.poll_voting_start = start_time;
+        ctx.accounts.poll_account.poll_voting_end = end_time;
+        ctx.accounts.poll_account.status = PollStatus::Active;
+        ctx.accounts.poll_account.governance_mint =
+            ctx.accounts.governance_mint.as_ref().map(|mint| mint.key());
+        ctx.accounts.poll_account.ballot_mode = ballot_mode;
+        ctx.accounts.poll_account.poll_reveal_end = reveal_end;
+        ctx.accounts.poll_account.eligibility_root = eligibility_root;
+        ctx.accounts.poll_account.quorum = quorum;
+        ctx.accounts.poll_account.pass_threshold_bps = pass_threshold_bps;
+        ctx.accounts.poll_account.max_choices = max_choices;
+        ctx.accounts.poll_account.registration_start = registration_start;
+        ctx.accounts.poll_account.credit_budget = credit_budget;
+        ctx.accounts.poll_account.gate_mints = gate_mints;
+
+        emit!(PollCreated {
+            poll_id,
+            authority: ctx.accounts.signer.key(),
+            ballot_mode,
+            start_time,
+            end_time,
+        });
+        Ok(())
+    }
+
+    pub fn update_poll(
+        ctx: Context<UpdatePoll>,
+        _poll_id: u64,
+        start_time: u64,
+        end_time: u64,
+        name: String,
+        description: String,
+    ) -> Result<()> {
+        let poll_account = &mut ctx.accounts.poll_account;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        if poll_account.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        if current_time > (poll_account.poll_voting_start as i64) {
+            return Err(ErrorCode::VotingAlreadyStarted.into());
+        }
+
+        if start_time >= end_time
+            || poll_account
+                .poll_reveal_end
+                .is_some_and(|reveal_end| reveal_end <= end_time)
+            || poll_account
+                .registration_start
+                .is_some_and(|registration_start| registration_start >= start_time)
+        {
+            return Err(ErrorCode::InvalidVotingWindow.into());
+        }
+
+        poll_account.poll_name = name;
+        poll_account.poll_description = description;
+        poll_account.poll_voting_start = start_time;
+        poll_account.poll_voting_end = end_time;
+        Ok(())
+    }
+
+    pub fn cancel_poll(ctx: Context<CancelPoll>, _poll_id: u64) -> Result<()> {
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        match poll_account.status {
+            PollStatus::Active => {}
+            PollStatus::Cancelled => return Err(ErrorCode::PollCancelled.into()),
+            PollStatus::Finalized => return Err(ErrorCode::PollFinalized.into()),
+        }
+
+        // Cancelling skips finalize_poll, so a poll whose proposal is being
+        // voted on could otherwise be withdrawn before it can fail.
+        let current_time = Clock::get()?.unix_timestamp;
+        if poll_account.has_proposal && current_time > (poll_account.poll_voting_start as i64) {
+            return Err(ErrorCode::VotingAlreadyStarted.into());
+        }
+
+        poll_account.status = PollStatus::Cancelled;
+        Ok(())
+    }
+
+    pub fn initialize_candidate(
+        ctx: Context<InitializeCandidate>,
+        poll_id: u64,
+        candidate: String,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        if poll_account.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        // Polls with a registration window only take candidates from
+        // `registration_start` until voting opens.
+        if let Some(registration_start) = poll_account.registration_start {
+            if current_time < (registration_start as i64) {
+                return Err(ErrorCode::RegistrationNotOpen.into());
+            }
+            if current_time > (poll_account.poll_voting_start as i64) {
+                return Err(ErrorCode::RegistrationClosed.into());
+            }
+        }
+
+        // A proposal was attached to exactly a yes and a no candidate.
+        if poll_account.has_proposal {
+            return Err(ErrorCode::NotYesNoCandidates.into());
+        }
+
+        if poll_account.ballot_mode == BallotMode::RankedChoice
+            && poll_account.poll_option_index as usize >= MAX_RANKED_CANDIDATES
+        {
+            return Err(ErrorCode::TooManyCandidates.into());
+        }
+
+        emit!(CandidateRegistered {
+            poll_id,
+            candidate_index: poll_account.poll_option_index,
+            candidate_name: candidate.clone(),
+        });
+
+        ctx.accounts.candidate_account.candidate_name = candidate;
+        ctx.accounts.candidate_account.candidate_index = poll_account.poll_option_index;
+        poll_account.poll_option_index += 1;
+        poll_account.candidate_count += 1;
+        Ok(())
+    }
+
+    /// Removes a candidate before voting opens and refunds its rent. The
+    /// index is not reused; ranked-choice polls mark it as eliminated.
+    pub fn remove_candidate(
+        ctx: Context<RemoveCandidate>,
+        poll_id: u64,
+        candidate_index: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        if poll_account.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        if current_time > (poll_account.poll_voting_start as i64) {
+            return Err(ErrorCode::VotingAlreadyStarted.into());
+        }
+
+        if poll_account.has_proposal {
+            return Err(ErrorCode::NotYesNoCandidates.into());
+        }
+
+        if poll_account.ballot_mode == BallotMode::RankedChoice {
+            poll_account.eliminated |= 1 << candidate_index;
+        }
+        poll_account.candidate_count -= 1;
+
+        emit!(CandidateRemoved {
+            poll_id,
+            candidate_index,
+        });
+        Ok(())
+    }
+
+    /// Casts the signer's vote. Remaining accounts may carry delegations to
+    /// the signer, each given as `[delegation, delegator_receipt]` — or
+    /// `[delegation, scoped_delegation, delegator_receipt]` for a global
+    /// delegation, so a poll-scoped delegation elsewhere takes precedence.
+    /// Every delegator who has not voted yet gets a receipt and adds one vote.
+    ///
+    /// `votes` must be 1, except in quadratic polls, where the signer can call
+    /// `vote` repeatedly to buy `votes` more votes for a candidate.
+    pub fn vote<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Vote<'info>>,
+        poll_id: u64,
+        candidate_index: u64,
+        votes: u64,
+        eligibility: Option<EligibilityProof>,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .poll_account
+            .require_voting_open(current_time)?;
+
+        if ctx.accounts.poll_account.ballot_mode == BallotMode::RankedChoice
+            || ctx.accounts.poll_account.poll_reveal_end.is_some()
+            || !ctx.accounts.poll_account.gate_mints.is_empty()
+        {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if ctx.accounts.poll_account.ballot_mode == BallotMode::Quadratic {
+            if !ctx.remaining_accounts.is_empty() {
+                return Err(ErrorCode::DelegationNotSupported.into());
+            }
+            return ctx.accounts.cast_quadratic_votes(
+                poll_id,
+                candidate_index,
+                votes,
+                eligibility.as_ref(),
+                ctx.bumps.voter_receipt,
+            );
+        }
+
+        if votes != 1 {
+            return Err(ErrorCode::InvalidVoteCount.into());
+        }
+
+        // The receipt is created on the first vote; if it already names a voter,
+        // this wallet has voted in this poll before.
+        if ctx.accounts.voter_receipt.voter != Pubkey::default() {
+            return Err(ErrorCode::AlreadyVoted.into());
+        }
+
+        let weight = match ctx.accounts.poll_account.governance_mint {
+            Some(_) => ctx.accounts.lock_governance_tokens()?,
+            None => ctx
+                .accounts
+                .poll_account
+                .eligible_weight(&ctx.accounts.signer.key(), eligibility.as_ref())?,
+        };
+
+        let delegated = if ctx.remaining_accounts.is_empty() {
+            0
+        } else {
+            ctx.accounts
+                .cast_delegated_votes(poll_id, candidate_index, ctx.remaining_accounts)?
+        };
+
+        ctx.accounts.candidate_account.candidate_votes += weight + delegated;
+        ctx.accounts.poll_account.record_ballots(1, weight);
+        ctx.accounts
+            .poll_account
+            .record_ballots(delegated, delegated);
+
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+        voter_receipt.poll_id = poll_id;
+        voter_receipt.voter = ctx.accounts.signer.key();
+        voter_receipt.candidate_index = Some(candidate_index);
+        voter_receipt.weight = weight + delegated;
+        voter_receipt.bump = ctx.bumps.voter_receipt;
+
+        emit!(VoteCast {
+            poll_id,
+            voter: voter_receipt.voter,
+            choices: vec![candidate_index],
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Casts `voter`'s vote from a ballot they signed off chain, with the
+    /// signer of the transaction (the relayer) paying the fees and rent. The
+    /// instruction right before this one must be an Ed25519 program
+    /// instruction verifying `voter`'s signature over
+    /// `relay_ballot_message(poll_id, candidate_index, nonce)`. `nonce` must be
+    /// the voter's next relay nonce, which this call consumes so the signed
+    /// ballot cannot be replayed.
+    pub fn relay_vote(
+        ctx: Context<RelayVote>,
+        poll_id: u64,
+        candidate_index: u64,
+        voter: Pubkey,
+        nonce: u64,
+        eligibility: Option<EligibilityProof>,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+        poll_account.require_voting_open(current_time)?;
+
+        // Token-weighted and NFT-gated ballots need the voter's own signature
+        // on their token accounts, so only plain plurality polls are relayed.
+        if poll_account.ballot_mode != BallotMode::Plurality
+            || poll_account.poll_reveal_end.is_some()
+            || poll_account.governance_mint.is_some()
+            || !poll_account.gate_mints.is_empty()
+        {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if nonce != ctx.accounts.relay_nonce.nonce {
+            return Err(ErrorCode::InvalidNonce.into());
+        }
+
+        let (signer, message) = verified_ed25519_message(&ctx.accounts.instructions)?;
+        if signer != voter || message != relay_ballot_message(poll_id, candidate_index, nonce) {
+            return Err(ErrorCode::InvalidRelaySignature.into());
+        }
+
+        if ctx.accounts.voter_receipt.voter != Pubkey::default() {
+            return Err(ErrorCode::AlreadyVoted.into());
+        }
+
+        let weight = poll_account.eligible_weight(&voter, eligibility.as_ref())?;
+
+        let relay_nonce = &mut ctx.accounts.relay_nonce;
+        relay_nonce.voter = voter;
+        relay_nonce.nonce += 1;
+        relay_nonce.bump = ctx.bumps.relay_nonce;
+
+        ctx.accounts.candidate_account.candidate_votes += weight;
+        poll_account.record_ballots(1, weight);
+
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+        voter_receipt.poll_id = poll_id;
+        voter_receipt.voter = voter;
+        voter_receipt.candidate_index = Some(candidate_index);
+        voter_receipt.weight = weight;
+        voter_receipt.bump = ctx.bumps.voter_receipt;
+
+        emit!(VoteCast {
+            poll_id,
+            voter,
+            choices: vec![candidate_index],
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the signer's single-choice vote to another candidate while
+    /// voting is open. The whole receipt weight moves, including votes the
+    /// signer cast for delegators.
+    pub fn change_vote(ctx: Context<ChangeVote>, poll_id: u64, candidate_index: u64) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .poll_account
+            .require_voting_open(current_time)?;
+
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+        let previous_candidate_index = voter_receipt.candidate_index.unwrap_or_default();
+        if previous_candidate_index == candidate_index {
+            return Err(ErrorCode::VoteUnchanged.into());
+        }
+
+        ctx.accounts.previous_candidate.candidate_votes -= voter_receipt.weight;
+        ctx.accounts.candidate_account.candidate_votes += voter_receipt.weight;
+        voter_receipt.candidate_index = Some(candidate_index);
+
+        emit!(VoteChanged {
+            poll_id,
+            voter: voter_receipt.voter,
+            previous_candidate_index,
+            candidate_index,
+            weight: voter_receipt.weight,
+        });
+        Ok(())
+    }
+
+    /// Records a sealed vote in a secret-ballot poll. `commitment` is
+    /// `sha256(candidate_index (little-endian u64) || salt || voter_pubkey)`; the vote only counts
+    /// once it is opened with `reveal_vote`.
+    pub fn commit_vote(
+        ctx: Context<CommitVote>,
+        poll_id: u64,
+        commitment: [u8; 32],
+        eligibility: Option<EligibilityProof>,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .poll_account
+            .require_voting_open(current_time)?;
+
+        if ctx.accounts.poll_account.poll_reveal_end.is_none() {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if ctx.accounts.voter_receipt.voter != Pubkey::default() {
+            return Err(ErrorCode::AlreadyVoted.into());
+        }
+
+        let weight = ctx
+            .accounts
+            .poll_account
+            .eligible_weight(&ctx.accounts.signer.key(), eligibility.as_ref())?;
+
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+        voter_receipt.poll_id = poll_id;
+        voter_receipt.voter = ctx.accounts.signer.key();
+        voter_receipt.weight = weight;
+        voter_receipt.commitment = Some(commitment);
+        voter_receipt.bump = ctx.bumps.voter_receipt;
+
+        Ok(())
+    }
+
+    pub fn reveal_vote(
+        ctx: Context<RevealVote>,
+        _poll_id: u64,
+        candidate_index: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+
+        let Some(reveal_end) = poll_account.poll_reveal_end else {
+            return Err(ErrorCode::WrongBallotMode.into());
+        };
+
+        if poll_account.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        if current_time <= (poll_account.poll_voting_end as i64) {
+            return Err(ErrorCode::VotingNotEnded.into());
+        }
+
+        if current_time > (reveal_end as i64) {
+            return Err(ErrorCode::RevealEnded.into());
+        }
+
+        let Some(commitment) = voter_receipt.commitment else {
+            return Err(ErrorCode::NoCommitment.into());
+        };
+
+        if voter_receipt.revealed {
+            return Err(ErrorCode::AlreadyRevealed.into());
+        }
+
+        let hash = hashv(&[
+            candidate_index.to_le_bytes().as_ref(),
+            salt.as_ref(),
+            ctx.accounts.signer.key().as_ref(),
+        ]);
+        if hash.to_bytes() != commitment {
+            return Err(ErrorCode::CommitmentMismatch.into());
+        }
+
+        ctx.accounts.candidate_account.candidate_votes += voter_receipt.weight;
+        poll_account.record_ballots(1, voter_receipt.weight);
+
+        voter_receipt.candidate_index = Some(candidate_index);
+        voter_receipt.revealed = true;
+
+        emit!(VoteCast {
+            poll_id: voter_receipt.poll_id,
+            voter: voter_receipt.voter,
+            choices: vec![candidate_index],
+            weight: voter_receipt.weight,
+        });
+
+        Ok(())
+    }
+
+    pub fn vote_ranked(
+        ctx: Context<VoteRanked>,
+        poll_id: u64,
+        rankings: Vec<u8>,
+        eligibility: Option<EligibilityProof>,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+        poll_account.require_voting_open(current_time)?;
+
+        if poll_account.ballot_mode != BallotMode::RankedChoice {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if ctx.accounts.voter_receipt.voter != Pubkey::default() {
+            return Err(ErrorCode::AlreadyVoted.into());
+        }
+
+        if rankings.is_empty() || rankings.len() > MAX_RANKED_CANDIDATES {
+            return Err(ErrorCode::InvalidRanking.into());
+        }
+
+        let mut ranked: u32 = 0;
+        for &index in rankings.iter() {
+            if index as u64 >= poll_account.poll_option_index
+                || poll_account.eliminated & (1 << index) != 0
+                || ranked & (1 << index) != 0
+            {
+                return Err(ErrorCode::InvalidRanking.into());
+            }
+            ranked |= 1 << index;
+        }
+
+        let weight =
+            poll_account.eligible_weight(&ctx.accounts.signer.key(), eligibility.as_ref())?;
+        poll_account.record_ballots(1, weight);
+
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+        voter_receipt.poll_id = poll_id;
+        voter_receipt.voter = ctx.accounts.signer.key();
+        voter_receipt.weight = weight;
+        voter_receipt.choices = rankings.into_iter().map(u16::from).collect();
+        voter_receipt.bump = ctx.bumps.voter_receipt;
+
+        emit!(VoteCast {
+            poll_id,
+            voter: voter_receipt.voter,
+            choices: voter_receipt
+                .choices
+                .iter()
+                .map(|&index| index.into())
+                .collect(),
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Counts a batch of ranked ballots (passed as writable remaining accounts)
+    /// towards the current round. Once every ballot has been counted, the round
+    /// is closed: a candidate holding a majority of the non-exhausted ballots
+    /// wins, otherwise the last-place candidate is eliminated and the next call
+    /// starts a new round.
+    pub fn tally_round<'info>(
+        ctx: Context<'_, '_, 'info, 'info, TallyRound<'info>>,
+        poll_id: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+        let round_account = &mut ctx.accounts.round_account;
+
+        if poll_account.ballot_mode != BallotMode::RankedChoice {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if poll_account.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        if current_time <= (poll_account.poll_voting_end as i64) {
+            return Err(ErrorCode::VotingNotEnded.into());
+        }
+
+        if poll_account.tally_complete {
+            return Err(ErrorCode::TallyComplete.into());
+        }
+
+        let round = poll_account.tally_round;
+        if round_account.counts.is_empty() {
+            round_account.poll_id = poll_id;
+            round_account.round = round;
+            round_account.counts = vec![0; poll_account.poll_option_index as usize];
+            round_account.bump = ctx.bumps.round_account;
+        }
+
+        for account_info in ctx.remaining_accounts.iter() {
+            let mut ballot = Account::<VoterReceipt>::try_from(account_info)?;
+
+            if ballot.poll_id != poll_id || ballot.choices.is_empty() {
+                return Err(ErrorCode::InvalidBallot.into());
+            }
+
+            if ballot.rounds_tallied != round {
+                return Err(ErrorCode::BallotAlreadyTallied.into());
+            }
+
+            let choice = ballot
+                .choices
+                .iter()
+                .find(|&&index| poll_account.eliminated & (1 << index) == 0);
+            let count = match choice {
+                Some(&index) => &mut round_account.counts[index as usize],
+                None => &mut round_account.exhausted,
+            };
+            *count = count
+                .checked_add(ballot.weight)
+                .ok_or(ErrorCode::TallyOverflow)?;
+
+            ballot.rounds_tallied = round + 1;
+            ballot.exit(&crate::ID)?;
+            round_account.ballots_counted += 1;
+        }
+
+        if round_account.ballots_counted < poll_account.total_ballots {
+            return Ok(());
+        }
+
+        // Allowlist weights are arbitrary u64s, so sum and compare in u128.
+        let active: u128 = round_account
+            .counts
+            .iter()
+            .map(|&count| count as u128)
+            .sum();
+        let remaining: Vec<usize> = (0..round_account.counts.len())
+            .filter(|&index| poll_account.eliminated & (1 << index) == 0)
+            .collect();
+
+        // Ties go to the lowest candidate index for the leader and to the
+        // highest index for elimination, so the outcome is deterministic.
+        let leader = remaining.iter().copied().max_by(|&a, &b| {
+            round_account.counts[a]
+                .cmp(&round_account.counts[b])
+                .then(b.cmp(&a))
+        });
+        let last = remaining.iter().copied().min_by(|&a, &b| {
+            round_account.counts[a]
+                .cmp(&round_account.counts[b])
+                .then(b.cmp(&a))
+        });
+
+        match (leader, last) {
+            (Some(leader), _) if round_account.counts[leader] as u128 * 2 > active => {
+                poll_account.tally_winner = Some(leader as u64);
+                poll_account.tally_complete = true;
+            }
+            (Some(_), Some(last)) if remaining.len() > 1 && active > 0 => {
+                poll_account.eliminated |= 1 << last;
+                round_account.eliminated = Some(last as u8);
+                poll_account.tally_round += 1;
+            }
+            _ => {
+                // No ballots left to count: the tally ends without a winner.
+                poll_account.tally_complete = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn unlock_tokens(ctx: Context<UnlockTokens>, poll_id: u64) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        if poll_account.status != PollStatus::Cancelled
+            && current_time <= (poll_account.poll_voting_end as i64)
+        {
+            return Err(ErrorCode::TokensLocked.into());
+        }
+
+        poll_account.open_vaults -= 1;
+
+        let poll_id_bytes = poll_id.to_le_bytes();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"poll".as_ref(),
+            poll_id_bytes.as_ref(),
+            &[ctx.bumps.poll_account],
+        ]];
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.key(),
+                TransferChecked {
+                    from: ctx.accounts.vote_vault.to_account_info(),
+                    mint: ctx.accounts.governance_mint.to_account_info(),
+                    to: ctx.accounts.voter_token_account.to_account_info(),
+                    authority: ctx.accounts.poll_account.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            ctx.accounts.vote_vault.amount,
+            ctx.accounts.governance_mint.decimals,
+        )?;
+
+        close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.key(),
+            CloseAccount {
+                account: ctx.accounts.vote_vault.to_account_info(),
+                destination: ctx.accounts.signer.to_account_info(),
+                authority: ctx.accounts.poll_account.to_account_info(),
+            },
+            &signer_seeds,
+        ))?;
+
+        Ok(())
+    }
+
+    /// Returns the tokens `vote_with_nft` escrowed for one `NftVote` record to
+    /// a token account of the signer and closes the record (rent → voter).
+    /// Like `unlock_tokens`, only once voting has ended or the poll was
+    /// cancelled. The last unlock for a mint also closes the poll's token
+    /// account for it, with its rent going to the signer.
+    pub fn unlock_nft(ctx: Context<UnlockNft>, poll_id: u64) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        if poll_account.status != PollStatus::Cancelled
+            && current_time <= (poll_account.poll_voting_end as i64)
+        {
+            return Err(ErrorCode::TokensLocked.into());
+        }
+
+        poll_account.open_vaults -= 1;
+
+        let poll_id_bytes = poll_id.to_le_bytes();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"poll".as_ref(),
+            poll_id_bytes.as_ref(),
+            &[ctx.bumps.poll_account],
+        ]];
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.key(),
+                TransferChecked {
+                    from: ctx.accounts.poll_vault.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.voter_token_account.to_account_info(),
+                    authority: ctx.accounts.poll_account.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            ctx.accounts.nft_vote.votes,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        ctx.accounts.poll_vault.reload()?;
+        if ctx.accounts.poll_vault.amount == 0 {
+            close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.key(),
+                CloseAccount {
+                    account: ctx.accounts.poll_vault.to_account_info(),
+                    destination: ctx.accounts.signer.to_account_info(),
+                    authority: ctx.accounts.poll_account.to_account_info(),
+                },
+                &signer_seeds,
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Casts one approval ballot for up to `max_choices` distinct candidates.
+    /// The candidate accounts are passed as writable remaining accounts in
+    /// the same order as their indices in `candidates`, and each receives the
+    /// voter's weight.
+    pub fn vote_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, VoteMany<'info>>,
+        poll_id: u64,
+        candidates: Vec<u64>,
+        eligibility: Option<EligibilityProof>,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+        poll_account.require_voting_open(current_time)?;
+
+        // Token-weighted polls lock the voter's tokens in `vote`, which this
+        // path does not, so their weight could be voted again from another wallet.
+        if poll_account.ballot_mode != BallotMode::Plurality
+            || poll_account.poll_reveal_end.is_some()
+            || poll_account.governance_mint.is_some()
+            || !poll_account.gate_mints.is_empty()
+        {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if ctx.accounts.voter_receipt.voter != Pubkey::default() {
+            return Err(ErrorCode::AlreadyVoted.into());
+        }
+
+        if candidates.is_empty() || candidates.len() > poll_account.max_choices as usize {
+            return Err(ErrorCode::InvalidChoices.into());
+        }
+
+        if ctx.remaining_accounts.len() != candidates.len() {
+            return Err(ErrorCode::InvalidCandidateAccount.into());
+        }
+
+        let weight =
+            poll_account.eligible_weight(&ctx.accounts.signer.key(), eligibility.as_ref())?;
+
+        let mut choices: Vec<u16> = Vec::with_capacity(candidates.len());
+        for (candidate_index, info) in candidates.iter().zip(ctx.remaining_accounts.iter()) {
+            let (address, _) = Pubkey::find_program_address(
+                &[
+                    poll_id.to_le_bytes().as_ref(),
+                    candidate_index.to_le_bytes().as_ref(),
+                ],
+                &crate::ID,
+            );
+            if info.key() != address {
+                return Err(ErrorCode::InvalidCandidateAccount.into());
+            }
+
+            let mut candidate_account = Account::<CandidateAccount>::try_from(info)?;
+            let index = u16::try_from(candidate_account.candidate_index)
+                .map_err(|_| ErrorCode::InvalidChoices)?;
+            if choices.contains(&index) {
+                return Err(ErrorCode::DuplicateChoice.into());
+            }
+
+            candidate_account.candidate_votes += weight;
+            candidate_account.exit(&crate::ID)?;
+            choices.push(index);
+        }
+
+        poll_account.record_ballots(1, weight);
+
+        let voter_receipt = &mut ctx.accounts.voter_receipt;
+        voter_receipt.poll_id = poll_id;
+        voter_receipt.voter = ctx.accounts.signer.key();
+        voter_receipt.weight = weight;
+        voter_receipt.choices = choices;
+        voter_receipt.bump = ctx.bumps.voter_receipt;
+
+        emit!(VoteCast {
+            poll_id,
+            voter: voter_receipt.voter,
+            choices: voter_receipt
+                .choices
+                .iter()
+                .map(|&index| index.into())
+                .collect(),
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Votes for `candidate_index` in an NFT-gated poll, with one vote per
+    /// token the signer holds in each token account used. For every token
+    /// account, pass the mint, the token account, the poll's associated token
+    /// account for the mint and the `["nft_vote", poll_id, mint, token_account]`
+    /// record as remaining accounts. The tokens are escrowed in the poll's
+    /// account until `unlock_nft`, so they cannot vote again from another
+    /// wallet while the poll is open.
+    pub fn vote_with_nft<'info>(
+        ctx: Context<'_, '_, 'info, 'info, VoteWithNft<'info>>,
+        poll_id: u64,
+        candidate_index: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        ctx.accounts
+            .poll_account
+            .require_voting_open(current_time)?;
+
+        if ctx.accounts.poll_account.gate_mints.is_empty() {
+            return Err(ErrorCode::WrongBallotMode.into());
+        }
+
+        if ctx.remaining_accounts.is_empty() {
+            return Err(ErrorCode::InvalidNftAccount.into());
+        }
+
+        let weight =
+            ctx.accounts
+                .use_gate_mints(poll_id, candidate_index, ctx.remaining_accounts)?;
+
+        ctx.accounts.candidate_account.candidate_votes += weight;
+        ctx.accounts.poll_account.record_ballots(1, weight);
+
+        emit!(VoteCast {
+            poll_id,
+            voter: ctx.accounts.signer.key(),
+            choices: vec![candidate_index],
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Lets `delegate` vote on the signer's behalf, either in one poll or, with
+    /// no `poll_id`, in every poll. Calling it again changes the delegate.
+    pub fn delegate(ctx: Context<Delegate>, poll_id: Option<u64>, delegate: Pubkey) -> Result<()> {
+        if delegate == ctx.accounts.signer.key() || poll_id == Some(GLOBAL_DELEGATION) {
+            return Err(ErrorCode::InvalidDelegation.into());
+        }
+
+        let delegation = &mut ctx.accounts.delegation;
+        delegation.delegator = ctx.accounts.signer.key();
+        delegation.delegate = delegate;
+        delegation.poll_id = poll_id;
+        delegation.bump = ctx.bumps.delegation;
+        Ok(())
+    }
+
+    pub fn undelegate(_ctx: Context<Undelegate>, _poll_id: Option<u64>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Records the result of a poll once voting (and, for secret ballots, the
+    /// reveal window) is over. Plurality polls must pass every
+    /// `CandidateAccount` as remaining accounts; ranked-choice polls take the
+    /// winner from the completed instant-runoff tally.
+    pub fn finalize_poll<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizePoll<'info>>,
+        poll_id: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        match poll_account.status {
+            PollStatus::Active => {}
+            PollStatus::Cancelled => return Err(ErrorCode::PollCancelled.into()),
+            PollStatus::Finalized => return Err(ErrorCode::PollFinalized.into()),
+        }
+
+        if current_time <= (poll_account.results_final_at() as i64) {
+            return Err(ErrorCode::VotingNotEnded.into());
+        }
+
+        if poll_account.ballot_mode == BallotMode::RankedChoice {
+            if !poll_account.tally_complete {
+                return Err(ErrorCode::TallyIncomplete.into());
+            }
+            poll_account.winner = poll_account.tally_winner;
+        } else {
+            let infos: Vec<_> = ctx.remaining_accounts.iter().collect();
+            let candidates = load_candidates(poll_id, poll_account, &infos)?;
+            // Threshold polls count candidate 0 as "yes" against the other.
+            if poll_account.pass_threshold_bps > 0
+                && (candidates.len() != 2
+                    || !candidates
+                        .iter()
+                        .any(|candidate| candidate.candidate_index == 0))
+            {
+                return Err(ErrorCode::NotYesNoCandidates.into());
+            }
+            let top_votes = candidates
+                .iter()
+                .map(|candidate| candidate.candidate_votes)
+                .max()
+                .unwrap_or(0);
+            let mut leaders = candidates
+                .iter()
+                .filter(|candidate| top_votes > 0 && candidate.candidate_votes == top_votes);
+
+            match (leaders.next(), leaders.next()) {
+                (Some(leader), None) => poll_account.winner = Some(leader.candidate_index),
+                (Some(_), Some(_)) => poll_account.is_tie = true,
+                _ => {}
+            }
+
+            if poll_account.pass_threshold_bps > 0 {
+                let summed: u64 = candidates.iter().map(|c| c.candidate_votes).sum();
+                let approvals = candidates
+                    .iter()
+                    .find(|candidate| candidate.candidate_index == 0)
+                    .map_or(0, |candidate| candidate.candidate_votes);
+                poll_account.outcome = Some(poll_account.governance_outcome(approvals, summed));
+            }
+        }
+
+        poll_account.status = PollStatus::Finalized;
+        Ok(())
+    }
+
+    /// Attaches instructions to a yes/no poll before voting opens. If the poll
+    /// passes, `execute_proposal` runs them signed by the poll's treasury PDA.
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        poll_id: u64,
+        instructions: Vec<ProposalInstruction>,
+        timelock: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &mut ctx.accounts.poll_account;
+
+        if poll_account.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        if current_time > (poll_account.poll_voting_start as i64) {
+            return Err(ErrorCode::VotingAlreadyStarted.into());
+        }
+
+        if poll_account.pass_threshold_bps == 0 {
+            return Err(ErrorCode::NotYesNoPoll.into());
+        }
+
+        // `yes_candidate` exists, so candidate 0 is one of the two. Candidates
+        // can't change once the proposal is attached.
+        if poll_account.candidate_count != 2 {
+            return Err(ErrorCode::NotYesNoCandidates.into());
+        }
+
+        if instructions.is_empty() {
+            return Err(ErrorCode::InvalidProposal.into());
+        }
+
+        poll_account.has_proposal = true;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.poll_id = poll_id;
+        proposal.instructions = instructions;
+        proposal.timelock = timelock;
+        proposal.bump = ctx.bumps.proposal;
+        Ok(())
+    }
+
+    /// Runs a passed poll's proposal once `timelock` seconds have gone by
+    /// since the results became final. Every account the instructions use,
+    /// including the programs they call, must be passed as remaining accounts.
+    pub fn execute_proposal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteProposal<'info>>,
+        poll_id: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let poll_account = &ctx.accounts.poll_account;
+        let proposal = &mut ctx.accounts.proposal;
+
+        if poll_account.outcome != Some(PollOutcome::Passed) {
+            return Err(ErrorCode::ProposalNotPassed.into());
+        }
+
+        if proposal.executed {
+            return Err(ErrorCode::ProposalAlreadyExecuted.into());
+        }
+
+        let executable_at = poll_account
+            .results_final_at()
+            .saturating_add(proposal.timelock);
+        if current_time <= (executable_at as i64) {
+            return Err(ErrorCode::TimelockActive.into());
+        }
+
+        proposal.executed = true;
+
+        let poll_id_bytes = poll_id.to_le_bytes();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"treasury".as_ref(),
+            poll_id_bytes.as_ref(),
+            &[ctx.bumps.treasury],
+        ]];
+
+        let mut account_infos = ctx.remaining_accounts.to_vec();
+        account_infos.push(ctx.accounts.treasury.to_account_info());
+
+        for proposal_instruction in proposal.instructions.iter() {
+            let instruction = Instruction {
+                program_id: proposal_instruction.program_id,
+                accounts: proposal_instruction
+                    .accounts
+                    .iter()
+                    .map(|meta| AccountMeta {
+                        pubkey: meta.pubkey,
+                        is_signer: meta.is_signer,
+                        is_writable: meta.is_writable,
+                    })
+                    .collect(),
+                data: proposal_instruction.data.clone(),
+            };
+            invoke_signed(&instruction, &account_infos, &signer_seeds)?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes a finalized or cancelled poll. Every `CandidateAccount` (and
+    /// optionally any `RoundTally`) must be passed as writable remaining
+    /// accounts; all of their rent goes back to the poll authority. A poll
+    /// with a proposal also closes it, but a passed proposal must have been
+    /// executed first. The treasury is left untouched, since anyone may have
+    /// funded it, and a `ClosedPoll` tombstone keeps the id from being
+    /// initialized again, since the poll's treasury, receipts and vaults are
+    /// all derived from it.
+    pub fn close_poll<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClosePoll<'info>>,
+        poll_id: u64,
+    ) -> Result<()> {
+        let poll_account = &ctx.accounts.poll_account;
+        let destination = ctx.accounts.signer.to_account_info();
+
+        if poll_account.status == PollStatus::Active {
+            return Err(ErrorCode::PollNotFinalized.into());
+        }
+
+        if poll_account.open_vaults > 0 {
+            return Err(ErrorCode::TokensStillLocked.into());
+        }
+
+        if poll_account.has_proposal {
+            let Some(proposal) = &ctx.accounts.proposal else {
+                return Err(ErrorCode::MissingProposal.into());
+            };
+            if poll_account.outcome == Some(PollOutcome::Passed) && !proposal.executed {
+                return Err(ErrorCode::ProposalNotExecuted.into());
+            }
+        }
+
+        let (candidate_infos, round_infos): (Vec<_>, Vec<_>) =
+            ctx.remaining_accounts.iter().partition(|info| {
+                info.try_borrow_data()
+                    .is_ok_and(|data| data.starts_with(CandidateAccount::DISCRIMINATOR))
+            });
+
+        for candidate in load_candidates(poll_id, poll_account, &candidate_infos)? {
+            candidate.close(destination.clone())?;
+        }
+
+        for info in round_infos {
+            let round_account = Account::<RoundTally>::try_from(info)?;
+            if round_account.poll_id != poll_id {
+                return Err(ErrorCode::InvalidCandidateAccount.into());
+            }
+            round_account.close(destination.clone())?;
+        }
+
+        ctx.accounts.closed_poll.poll_id = poll_id;
+        Ok(())
+    }
+}
+
+/// Deserializes the poll's candidate accounts from `infos`, checking that
+/// each one belongs to the poll and that every remaining candidate appears
+/// exactly once.
+fn load_candidates<'info>(
+    poll_id: u64,
+    poll_account: &PollAccount,
+    infos: &[&'info AccountInfo<'info>],
+) -> Result<Vec<Account<'info, CandidateAccount>>> {
+    if infos.len() as u64 != poll_account.candidate_count {
+        return Err(ErrorCode::MissingCandidates.into());
+    }
+
+    let mut seen = vec![false; poll_account.poll_option_index as usize];
+    let mut candidates = Vec::with_capacity(infos.len());
+    for info in infos {
+        let candidate = Account::<CandidateAccount>::try_from(info)?;
+        let (address, _) = Pubkey::find_program_address(
+            &[
+                poll_id.to_le_bytes().as_ref(),
+                candidate.candidate_index.to_le_bytes().as_ref(),
+            ],
+            &crate::ID,
+        );
+        if address != info.key() {
+            return Err(ErrorCode::InvalidCandidateAccount.into());
+        }
+
+        let index = candidate.candidate_index as usize;
+        if index >= seen.len() || seen[index] {
+            return Err(ErrorCode::InvalidCandidateAccount.into());
+        }
+        seen[index] = true;
+        candidates.push(candidate);
+    }
+
+    Ok(candidates)
+}
+
+/// Creates the program-owned PDA at `target` with the given seeds and writes
+/// `data` into it, paid for by `payer`. Works even if someone has already
+/// sent lamports to the address.
+fn create_program_account<'info, T: AccountSerialize + Space>(
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    target: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    data: &T,
+) -> Result<()> {
+    let space = 8 + T::INIT_SPACE;
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer_seeds = [seeds];
+
+    if target.lamports() == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.key(),
+                CreateAccount {
+                    from: payer.to_account_info(),
+                    to: target.clone(),
+                },
+                &signer_seeds,
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        )?;
+    } else {
+        let top_up = rent.saturating_sub(target.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.key(),
+                    Transfer {
+                        from: payer.to_account_info(),
+                        to: target.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.key(),
+                Allocate {
+                    account_to_allocate: target.clone(),
+                },
+                &signer_seeds,
+            ),
+            space as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.key(),
+                Assign {
+                    account_to_assign: target.clone(),
+                },
+                &signer_seeds,
+            ),
+            &crate::ID,
+        )?;
+    }
+
+    let mut account_data = target.try_borrow_mut_data()?;
+    data.try_serialize(&mut &mut account_data[..])?;
+    Ok(())
+}
+
+/// Credits spent once a quadratic voter moves a candidate's allocation from
+/// `previous` to `previous + votes`: `credits_spent + (previous + votes)² -
+/// previous²`. `None` if that does not fit in a `u64`, which no budget covers.
+fn quadratic_credits(credits_spent: u64, previous: u64, votes: u64) -> Option<u64> {
+    let allocated = previous.checked_add(votes)?;
+    let cost = allocated.checked_mul(allocated)? - previous * previous;
+    credits_spent.checked_add(cost)
+}
+
+/// Reads the Ed25519 program instruction right before the current one and
+/// returns the public key and message whose signature it verified. Only a
+/// single signature is accepted, and its key, message and signature must all
+/// sit in that instruction's own data.
+fn verified_ed25519_message(instructions: &AccountInfo) -> Result<(Pubkey, Vec<u8>)> {
+    let current_index = load_current_index_checked(instructions)?;
+    if current_index == 0 {
+        return Err(ErrorCode::MissingRelaySignature.into());
+    }
+
+    let ed25519_ix = load_instruction_at_checked(current_index as usize - 1, instructions)?;
+    if ed25519_ix.program_id != ED25519_PROGRAM_ID || !ed25519_ix.accounts.is_empty() {
+        return Err(ErrorCode::MissingRelaySignature.into());
+    }
+
+    // Layout: signature count (u8), padding (u8), then per signature the u16
+    // offsets `signature`, `signature_ix`, `public_key`, `public_key_ix`,
+    // `message`, `message_size`, `message_ix`.
+    let data = &ed25519_ix.data;
+    if data.len() < 16 || data[0] != 1 {
+        return Err(ErrorCode::InvalidRelaySignature.into());
+    }
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    if [read_u16(4), read_u16(8), read_u16(14)]
+        .iter()
+        .any(|&ix_index| ix_index != u16::MAX)
+    {
+        return Err(ErrorCode::InvalidRelaySignature.into());
+    }
+
+    let public_key_bytes = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(ErrorCode::InvalidRelaySignature)?;
+    let message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(ErrorCode::InvalidRelaySignature)?;
+
+    let public_key =
+        Pubkey::try_from(public_key_bytes).map_err(|_| ErrorCode::InvalidRelaySignature)?;
+
+    Ok((public_key, message.to_vec()))
+}
+
+impl<'info> Vote<'info> {
+    /// Walks the delegation accounts passed to `vote` and casts a vote for
+    /// `candidate_index` on behalf of each delegator by creating their receipt.
+    /// Delegators who already hold a receipt (they voted, or another delegate
+    /// counted them) are skipped, so nobody is counted twice. Returns the
+    /// number of delegated votes cast.
+    fn cast_delegated_votes(
+        &self,
+        poll_id: u64,
+        candidate_index: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<u64> {
+        if self.poll_account.governance_mint.is_some()
+            || self.poll_account.eligibility_root.is_some()
+        {
+            return Err(ErrorCode::DelegationNotSupported.into());
+        }
+
+        let mut accounts = remaining_accounts.iter();
+        let mut delegated = 0;
+        while let Some(delegation_info) = accounts.next() {
+            let delegation = Account::<Delegation>::try_from(delegation_info)?;
+            if delegation.delegate != self.signer.key() {
+                return Err(ErrorCode::InvalidDelegation.into());
+            }
+
+            let overridden = match delegation.poll_id {
+                Some(scope) if scope == poll_id => false,
+                Some(_) => return Err(ErrorCode::InvalidDelegation.into()),
+                None => {
+                    let scoped_info = accounts.next().ok_or(ErrorCode::InvalidDelegation)?;
+                    let (scoped_address, _) = Pubkey::find_program_address(
+                        &[
+                            b"delegation".as_ref(),
+                            delegation.delegator.as_ref(),
+                            poll_id.to_le_bytes().as_ref(),
+                        ],
+                        &crate::ID,
+                    );
+                    if scoped_info.key() != scoped_address {
+                        return Err(ErrorCode::InvalidDelegation.into());
+                    }
+                    !scoped_info.data_is_empty()
+                }
+            };
+
+            let receipt_info = accounts.next().ok_or(ErrorCode::InvalidDelegation)?;
+            let (receipt_address, bump) = Pubkey::find_program_address(
+                &[
+                    b"receipt".as_ref(),
+                    poll_id.to_le_bytes().as_ref(),
+                    delegation.delegator.as_ref(),
+                ],
+                &crate::ID,
+            );
+            if receipt_info.key() != receipt_address {
+                return Err(ErrorCode::InvalidDelegation.into());
+            }
+
+            if overridden || !receipt_info.data_is_empty() {
+                continue;
+            }
+
+            self.create_delegated_receipt(
+                receipt_info,
+                VoterReceipt {
+                    poll_id,
+                    voter: delegation.delegator,
+                    candidate_index: None,
+                    weight: 1,
+                    choices: Vec::new(),
+                    allocations: Vec::new(),
+                    credit_budget: 0,
+                    credits_spent: 0,
+                    rounds_tallied: 0,
+                    commitment: None,
+                    revealed: false,
+                    delegated_to: Some(self.signer.key()),
+                    bump,
+                },
+            )?;
+            emit!(VoteCast {
+                poll_id,
+                voter: delegation.delegator,
+                choices: vec![candidate_index],
+                weight: 1,
+            });
+            delegated += 1;
+        }
+
+        Ok(delegated)
+    }
+
+    /// Creates a delegator's receipt PDA, paid for by the delegate.
+    fn create_delegated_receipt(
+        &self,
+        receipt_info: &'info AccountInfo<'info>,
+        receipt: VoterReceipt,
+    ) -> Result<()> {
+        let poll_id_bytes = receipt.poll_id.to_le_bytes();
+        create_program_account(
+            &self.signer,
+            &self.system_program,
+            receipt_info,
+            &[
+                b"receipt".as_ref(),
+                poll_id_bytes.as_ref(),
+                receipt.voter.as_ref(),
+                &[receipt.bump],
+            ],
+            &receipt,
+        )
+    }
+
+    /// Adds `votes` votes for `candidate_index` to the signer's quadratic
+    /// ballot. The receipt tracks votes per candidate, so raising a
+    /// candidate's votes from `n` to `m` costs `m² - n²` credits, and the
+    /// total spent can never exceed the voter's budget.
+    fn cast_quadratic_votes(
+        &mut self,
+        poll_id: u64,
+        candidate_index: u64,
+        votes: u64,
+        eligibility: Option<&EligibilityProof>,
+        bump: u8,
+    ) -> Result<()> {
+        if votes == 0 {
+            return Err(ErrorCode::InvalidVoteCount.into());
+        }
+
+        let first_ballot = self.voter_receipt.voter == Pubkey::default();
+        if first_ballot {
+            let weight = self
+                .poll_account
+                .eligible_weight(&self.signer.key(), eligibility)?;
+            self.voter_receipt.poll_id = poll_id;
+            self.voter_receipt.voter = self.signer.key();
+            self.voter_receipt.credit_budget =
+                self.poll_account.credit_budget.saturating_mul(weight);
+            self.voter_receipt.bump = bump;
+        }
+
+        let choice = u16::try_from(candidate_index).map_err(|_| ErrorCode::InvalidChoices)?;
+        let voter_receipt = &mut self.voter_receipt;
+        let position = match voter_receipt
+            .choices
+            .iter()
+            .position(|&index| index == choice)
+        {
+            Some(position) => position,
+            None if voter_receipt.choices.len() < MAX_CHOICES => {
+                voter_receipt.choices.push(choice);
+                voter_receipt.allocations.push(0);
+                voter_receipt.choices.len() - 1
+            }
+            None => return Err(ErrorCode::InvalidChoices.into()),
+        };
+
+        let previous = voter_receipt.allocations[position];
+        let spent = quadratic_credits(voter_receipt.credits_spent, previous, votes)
+            .filter(|&spent| spent <= voter_receipt.credit_budget)
+            .ok_or(ErrorCode::InsufficientCredits)?;
+
+        voter_receipt.allocations[position] = previous + votes;
+        voter_receipt.credits_spent = spent;
+        voter_receipt.weight += votes;
+        self.candidate_account.candidate_votes += votes;
+        self.poll_account
+            .record_ballots(u64::from(first_ballot), votes);
+
+        emit!(VoteCast {
+            poll_id,
+            voter: voter_receipt.voter,
+            choices: vec![candidate_index],
+            weight: votes,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the voter's whole governance token balance into their vote vault
+    /// and returns what the vault received as the vote weight. The tokens stay there until
+    /// `unlock_tokens` is called after the poll ends, so they cannot be
+    /// transferred to another wallet and voted again.
+    fn lock_governance_tokens(&mut self) -> Result<u64> {
+        let (
+            Some(governance_mint),
+            Some(voter_token_account),
+            Some(vote_vault),
+            Some(token_program),
+        ) = (
+            self.governance_mint.as_ref(),
+            self.voter_token_account.as_ref(),
+            self.vote_vault.as_mut(),
+            self.token_program.as_ref(),
+        )
+        else {
+            return Err(ErrorCode::MissingGovernanceAccounts.into());
+        };
+
+        let amount = voter_token_account.amount;
+        if amount == 0 {
+            return Err(ErrorCode::NoVotingPower.into());
+        }
+
+        self.poll_account.open_vaults += 1;
+
+        // Like `vote_with_nft`, weigh the vote by what reaches the vault in
+        // case the mint charges a transfer fee; `unlock_tokens` returns that.
+        let locked_before = vote_vault.amount;
+        transfer_checked(
+            CpiContext::new(
+                token_program.key(),
+                TransferChecked {
+                    from: voter_token_account.to_account_info(),
+                    mint: governance_mint.to_account_info(),
+                    to: vote_vault.to_account_info(),
+                    authority: self.signer.to_account_info(),
+                },
+            ),
+            amount,
+            governance_mint.decimals,
+        )?;
+
+        vote_vault.reload()?;
+        let weight = vote_vault.amount - locked_before;
+        if weight == 0 {
+            return Err(ErrorCode::NoVotingPower.into());
+        }
+        Ok(weight)
+    }
+}
+
+impl<'info> VoteWithNft<'info> {
+    /// Checks each group of accounts passed to `vote_with_nft`, moves the
+    /// token account's balance into the poll's account for the mint and
+    /// creates the record of the escrowed tokens. Returns the number of
+    /// tokens escrowed, which is the vote's weight.
+    fn use_gate_mints(
+        &mut self,
+        poll_id: u64,
+        candidate_index: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<u64> {
+        let poll_id_bytes = poll_id.to_le_bytes();
+        let poll_info = self.poll_account.to_account_info();
+        let mut weight: u64 = 0;
+        for accounts in remaining_accounts.chunks(4) {
+            let [mint_info, token_account_info, vault_info, record_info] = accounts else {
+                return Err(ErrorCode::InvalidNftAccount.into());
+            };
+            let mint = InterfaceAccount::<Mint>::try_from(mint_info)?;
+            let token_account = InterfaceAccount::<TokenAccount>::try_from(token_account_info)?;
+            if token_account.owner != self.signer.key()
+                || token_account.mint != mint.key()
+                || !self.poll_account.gate_mints.contains(&token_account.mint)
+                || *mint_info.owner != self.token_program.key()
+            {
+                return Err(ErrorCode::InvalidNftAccount.into());
+            }
+
+            if token_account.amount == 0 {
+                return Err(ErrorCode::NoVotingPower.into());
+            }
+
+            let vault_address = get_associated_token_address_with_program_id(
+                poll_info.key,
+                mint_info.key,
+                self.token_program.key,
+            );
+            let (record_address, bump) = Pubkey::find_program_address(
+                &[
+                    b"nft_vote".as_ref(),
+                    poll_id_bytes.as_ref(),
+                    mint_info.key.as_ref(),
+                    token_account_info.key.as_ref(),
+                ],
+                &crate::ID,
+            );
+            if vault_info.key() != vault_address || record_info.key() != record_address {
+                return Err(ErrorCode::InvalidNftAccount.into());
+            }
+
+            // Also catches the same token account passed twice in one call.
+            if !record_info.data_is_empty() {
+                return Err(ErrorCode::NftAlreadyUsed.into());
+            }
+
+            create_idempotent(CpiContext::new(
+                self.associated_token_program.key(),
+                Create {
+                    payer: self.signer.to_account_info(),
+                    associated_token: vault_info.clone(),
+                    authority: poll_info.clone(),
+                    mint: mint_info.clone(),
+                    system_program: self.system_program.to_account_info(),
+                    token_program: self.token_program.to_account_info(),
+                },
+            ))?;
+
+            // Count what reaches the escrow, in case the mint charges a
+            // transfer fee; `unlock_nft` returns exactly that amount.
+            let escrowed_before = InterfaceAccount::<TokenAccount>::try_from(vault_info)?.amount;
+            transfer_checked(
+                CpiContext::new(
+                    self.token_program.key(),
+                    TransferChecked {
+                        from: token_account_info.clone(),
+                        mint: mint_info.clone(),
+                        to: vault_info.clone(),
+                        authority: self.signer.to_account_info(),
+                    },
+                ),
+                token_account.amount,
+                mint.decimals,
+            )?;
+            let votes =
+                InterfaceAccount::<TokenAccount>::try_from(vault_info)?.amount - escrowed_before;
+
+            create_program_account(
+                &self.signer,
+                &self.system_program,
+                record_info,
+                &[
+                    b"nft_vote".as_ref(),
+                    poll_id_bytes.as_ref(),
+                    mint_info.key.as_ref(),
+                    token_account_info.key.as_ref(),
+                    &[bump],
+                ],
+                &NftVote {
+                    poll_id,
+                    mint: mint.key(),
+                    token_account: token_account_info.key(),
+                    voter: self.signer.key(),
+                    candidate_index,
+                    votes,
+                    bump,
+                },
+            )?;
+            self.poll_account.open_vaults += 1;
+            weight += votes;
+        }
+
+        Ok(weight)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct InitializePoll<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + PollAccount::INIT_SPACE,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    /// CHECK: only checked to be empty; a closed poll's id cannot be reused.
+    #[account(
+        seeds = [b"closed_poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = closed_poll.data_is_empty() @ ErrorCode::PollIdRetired,
+    )]
+    pub closed_poll: UncheckedAccount<'info>,
+
+    /// Set to make this a token-weighted poll.
+    #[account(mint::token_program = token_program)]
+    pub governance_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct UpdatePoll<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = poll_account.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct CancelPoll<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = poll_account.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct InitializeCandidate<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = poll_account.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + CandidateAccount::INIT_SPACE,
+        seeds = [
+            poll_id.to_le_bytes().as_ref(),
+            poll_account.poll_option_index.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64, candidate_index: u64)]
+pub struct RemoveCandidate<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = poll_account.authority == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        mut,
+        close = signer,
+        seeds = [poll_id.to_le_bytes().as_ref(), candidate_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64, candidate_index: u64)]
+pub struct Vote<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        mut,
+        seeds = [poll_id.to_le_bytes().as_ref(), candidate_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + VoterReceipt::INIT_SPACE,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+
+    // Token-weighted polls only: the voter's balance is locked in `vote_vault`.
+    #[account(
+        constraint = poll_account.governance_mint == Some(governance_mint.key()) @ ErrorCode::InvalidGovernanceMint,
+        mint::token_program = token_program,
+    )]
+    pub governance_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = governance_mint,
+        token::authority = signer,
+        token::token_program = token_program,
+    )]
+    pub voter_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        seeds = [b"vault".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump,
+        token::mint = governance_mint,
+        token::authority = poll_account,
+        token::token_program = token_program,
+    )]
+    pub vote_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64, candidate_index: u64, voter: Pubkey)]
+pub struct RelayVote<'info> {
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        mut,
+        seeds = [poll_id.to_le_bytes().as_ref(), candidate_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = 8 + VoterReceipt::INIT_SPACE,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), voter.as_ref()],
+        bump
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = 8 + RelayNonce::INIT_SPACE,
+        seeds = [b"nonce".as_ref(), voter.as_ref()],
+        bump
+    )]
+    pub relay_nonce: Account<'info, RelayNonce>,
+
+    /// CHECK: the instructions sysvar, checked by address.
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64, candidate_index: u64)]
+pub struct ChangeVote<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    // Checked before `previous_candidate`, whose seeds depend on it.
+    #[account(
+        mut,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump = voter_receipt.bump,
+        constraint = voter_receipt.candidate_index.is_some() @ ErrorCode::NoVoteToChange,
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+
+    #[account(
+        mut,
+        seeds = [
+            poll_id.to_le_bytes().as_ref(),
+            voter_receipt.candidate_index.unwrap_or_default().to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub previous_candidate: Account<'info, CandidateAccount>,
+
+    #[account(
+        mut,
+        seeds = [poll_id.to_le_bytes().as_ref(), candidate_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct CommitVote<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + VoterReceipt::INIT_SPACE,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64, candidate_index: u64)]
+pub struct RevealVote<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        mut,
+        seeds = [poll_id.to_le_bytes().as_ref(), candidate_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump = voter_receipt.bump,
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct VoteRanked<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + VoterReceipt::INIT_SPACE,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct TallyRound<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + RoundTally::INIT_SPACE,
+        seeds = [b"round".as_ref(), poll_id.to_le_bytes().as_ref(), &[poll_account.tally_round]],
+        bump
+    )]
+    pub round_account: Account<'info, RoundTally>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct UnlockTokens<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        constraint = poll_account.governance_mint == Some(governance_mint.key()) @ ErrorCode::InvalidGovernanceMint,
+        mint::token_program = token_program,
+    )]
+    pub governance_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = governance_mint,
+        token::authority = signer,
+        token::token_program = token_program,
+    )]
+    pub voter_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump,
+        token::mint = governance_mint,
+        token::authority = poll_account,
+        token::token_program = token_program,
+    )]
+    pub vote_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct VoteMany<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + VoterReceipt::INIT_SPACE,
+        seeds = [b"receipt".as_ref(), poll_id.to_le_bytes().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub voter_receipt: Account<'info, VoterReceipt>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64, candidate_index: u64)]
+pub struct VoteWithNft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
 
-    pub fn initialize_poll(
-        ctx: Context<InitializePoll>,
-        _poll_id: u64,
-        start_time: u64,
-        end_time: u64,
-        name: String,
-        description: String,
-    ) -> Result<()> {
-        ctx.accounts.poll_account.poll_name = name;
-        ctx.accounts.poll_account.poll_description = description;
-        ctx.accounts.poll_account.poll_voting_start = start_time;
-        ctx.accounts.poll_account.poll_voting_end = end_time;
-        Ok(())
-    }
+    #[account(
+        mut,
+        seeds = [poll_id.to_le_bytes().as_ref(), candidate_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub candidate_account: Account<'info, CandidateAccount>,
 
-    pub fn initialize_candidate(
-        ctx: Context<InitializeCandidate>,
-        _poll_id: u64,
-        candidate: String,
-    ) -> Result<()> {
-        ctx.accounts.candidate_account.candidate_name = candidate;
-        ctx.accounts.poll_account.poll_option_index += 1;
-        Ok(())
-    }
+    /// Token program of the gate mints used in this call.
+    pub token_program: Interface<'info, TokenInterface>,
 
-    pub fn vote(ctx: Context<Vote>, _poll_id: u64, _candidate: String) -> Result<()> {
-        let candidate_account = &mut ctx.accounts.candidate_account;
-        let current_time = Clock::get()?.unix_timestamp;
+    pub associated_token_program: Program<'info, AssociatedToken>,
 
-        if current_time > (ctx.accounts.poll_account.poll_voting_end as i64) {
-            return Err(ErrorCode::VotingEnded.into());
-        }
+    pub system_program: Program<'info, System>,
+}
 
-        if current_time <= (ctx.accounts.poll_account.poll_voting_start as i64) {
-            return Err(ErrorCode::VotingNotStarted.into());
-        }
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct UnlockNft<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
 
-        candidate_account.candidate_votes += 1;
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
 
-        Ok(())
-    }
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Receives the tokens; any of the signer's accounts for the mint.
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = signer,
+        token::token_program = token_program,
+    )]
+    pub voter_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = poll_account,
+        associated_token::token_program = token_program,
+    )]
+    pub poll_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        close = signer,
+        seeds = [
+            b"nft_vote".as_ref(),
+            poll_id.to_le_bytes().as_ref(),
+            mint.key().as_ref(),
+            nft_vote.token_account.as_ref(),
+        ],
+        bump = nft_vote.bump,
+        constraint = nft_vote.voter == signer.key() @ ErrorCode::InvalidNftAccount,
+    )]
+    pub nft_vote: Account<'info, NftVote>,
+
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-#[instruction(poll_id: u64)]
-pub struct InitializePoll<'info> {
+#[instruction(poll_id: Option<u64>)]
+pub struct Delegate<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
     #[account(
         init_if_needed,
         payer = signer,
-        space = 8 + PollAccount::INIT_SPACE,
-        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        space = 8 + Delegation::INIT_SPACE,
+        seeds = [
+            b"delegation".as_ref(),
+            signer.key().as_ref(),
+            poll_id.unwrap_or(GLOBAL_DELEGATION).to_le_bytes().as_ref(),
+        ],
         bump
     )]
-    pub poll_account: Account<'info, PollAccount>,
+    pub delegation: Account<'info, Delegation>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(poll_id: u64, candidate: String)]
-pub struct InitializeCandidate<'info> {
+#[instruction(poll_id: Option<u64>)]
+pub struct Undelegate<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        close = signer,
+        seeds = [
+            b"delegation".as_ref(),
+            signer.key().as_ref(),
+            poll_id.unwrap_or(GLOBAL_DELEGATION).to_le_bytes().as_ref(),
+        ],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, Delegation>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct FinalizePoll<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct ClosePoll<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
     #[account(
         mut,
+        close = signer,
         seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
         bump,
+        constraint = poll_account.authority == signer.key() @ ErrorCode::Unauthorized,
     )]
     pub poll_account: Account<'info, PollAccount>,
 
+    #[account(
+        mut,
+        close = signer,
+        seeds = [b"proposal".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Option<Account<'info, Proposal>>,
+
     #[account(
         init,
         payer = signer,
-        space = 8 + CandidateAccount::INIT_SPACE,
-        seeds = [poll_id.to_le_bytes().as_ref(), candidate.as_ref()],
+        space = 8 + ClosedPoll::INIT_SPACE,
+        seeds = [b"closed_poll".as_ref(), poll_id.to_le_bytes().as_ref()],
         bump
     )]
-    pub candidate_account: Account<'info, CandidateAccount>,
+    pub closed_poll: Account<'info, ClosedPoll>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(poll_id: u64, candidate: String)]
-pub struct Vote<'info> {
+#[instruction(poll_id: u64, instructions: Vec<ProposalInstruction>)]
+pub struct CreateProposal<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
@@ -102,28 +2317,159 @@ pub struct Vote<'info> {
         mut,
         seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
         bump,
+        constraint = poll_account.authority == signer.key() @ ErrorCode::Unauthorized,
     )]
     pub poll_account: Account<'info, PollAccount>,
 
     #[account(
-        mut,
-        seeds = [poll_id.to_le_bytes().as_ref(), candidate.as_ref()],
+        seeds = [poll_id.to_le_bytes().as_ref(), 0u64.to_le_bytes().as_ref()],
         bump
     )]
-    pub candidate_account: Account<'info, CandidateAccount>,
+    pub yes_candidate: Account<'info, CandidateAccount>,
+
+    #[account(
+        init,
+        payer = signer,
+        space = Proposal::space(&instructions),
+        seeds = [b"proposal".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(poll_id: u64)]
+pub struct ExecuteProposal<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"poll".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub poll_account: Account<'info, PollAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, Proposal>,
+
+    /// Holds the poll's funds and signs the proposal's instructions.
+    #[account(
+        mut,
+        seeds = [b"treasury".as_ref(), poll_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub treasury: SystemAccount<'info>,
 }
 
 #[account]
 #[derive(InitSpace)]
 pub struct CandidateAccount {
-    #[max_len(32)]
+    #[max_len(64)]
     pub candidate_name: String,
     pub candidate_votes: u64,
+    pub candidate_index: u64,
+}
+
+/// Optional voting rules chosen when a poll is created.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PollSettings {
+    pub ballot_mode: BallotMode,
+    /// Makes the poll a secret ballot revealed until this timestamp.
+    pub reveal_end: Option<u64>,
+    /// Restricts voting to wallets in this Merkle tree (see `eligibility_leaf`).
+    pub eligibility_root: Option<[u8; 32]>,
+    /// Minimum summed votes for a yes/no poll to count; 0 for no quorum.
+    pub quorum: u64,
+    /// Share of the votes (in basis points) candidate 0 needs for a yes/no
+    /// poll to pass; 0 for polls that are not yes/no.
+    pub pass_threshold_bps: u16,
+    /// How many candidates one `vote_many` ballot may select; 1 for
+    /// single-choice polls.
+    pub max_choices: u8,
+    /// Opens candidate registration at this timestamp and closes it when
+    /// voting starts; `None` lets candidates be added at any time.
+    pub registration_start: Option<u64>,
+    /// Credits each voter can spend in a quadratic poll; 0 for other polls.
+    pub credit_budget: u64,
+    /// Makes the poll NFT-gated: only holders of these mints can vote, through
+    /// `vote_with_nft`. Empty for other polls.
+    pub gate_mints: Vec<Pubkey>,
+}
+
+/// Proof that the signer is on a poll's allowlist with the given weight.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EligibilityProof {
+    pub weight: u64,
+    pub proof: Vec<[u8; 32]>,
+}
+
+/// Message a voter signs off chain for `relay_vote`:
+/// `program_id || poll_id || candidate_index || nonce`, integers as
+/// little-endian u64. The program ID keeps the ballot from being replayed
+/// against another deployment.
+pub fn relay_ballot_message(poll_id: u64, candidate_index: u64, nonce: u64) -> Vec<u8> {
+    [
+        crate::ID.as_ref(),
+        &poll_id.to_le_bytes(),
+        &candidate_index.to_le_bytes(),
+        &nonce.to_le_bytes(),
+    ]
+    .concat()
+}
+
+/// Leaf of an allowlist tree: `sha256(0x00 || voter || weight (little-endian u64))`.
+/// Allowlists without per-voter weights use a weight of 1.
+pub fn eligibility_leaf(voter: &Pubkey, weight: u64) -> [u8; 32] {
+    hashv(&[&[0u8], voter.as_ref(), &weight.to_le_bytes()]).to_bytes()
+}
+
+/// Walks a proof up to the root. Inner nodes are `sha256(0x01 || a || b)`
+/// with the two children sorted, so proofs do not need left/right flags.
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        let (a, b) = if node <= *sibling {
+            (node, *sibling)
+        } else {
+            (*sibling, node)
+        };
+        hashv(&[&[1u8], &a, &b]).to_bytes()
+    });
+    computed == *root
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum BallotMode {
+    /// One candidate per ballot, counted straight into `candidate_votes`.
+    Plurality,
+    /// Ordered ballots resolved by instant-runoff with `tally_round`.
+    RankedChoice,
+    /// Voters spend a credit budget; `n` votes for one candidate cost `n²`.
+    Quadratic,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum PollOutcome {
+    Passed,
+    Failed,
+    QuorumNotMet,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum PollStatus {
+    Active,
+    Cancelled,
+    Finalized,
 }
 
 #[account]
 #[derive(InitSpace)]
 pub struct PollAccount {
+    pub authority: Pubkey,
     #[max_len(32)]
     pub poll_name: String,
     #[max_len(280)]
@@ -131,6 +2477,291 @@ pub struct PollAccount {
     pub poll_voting_start: u64,
     pub poll_voting_end: u64,
     pub poll_option_index: u64,
+    pub status: PollStatus,
+    pub governance_mint: Option<Pubkey>,
+    pub ballot_mode: BallotMode,
+    pub total_ballots: u64,
+    pub tally_round: u8,
+    pub eliminated: u32,
+    pub tally_complete: bool,
+    pub tally_winner: Option<u64>,
+    /// Set for secret-ballot polls: commits are taken until `poll_voting_end`
+    /// and revealed until this timestamp.
+    pub poll_reveal_end: Option<u64>,
+    pub eligibility_root: Option<[u8; 32]>,
+    /// Vote vaults of a token-weighted poll whose tokens are still locked.
+    pub open_vaults: u64,
+    /// Sum of the weights of all counted ballots.
+    pub total_votes: u64,
+    /// Set by `finalize_poll`; `None` if there were no votes or a tie.
+    pub winner: Option<u64>,
+    pub is_tie: bool,
+    pub quorum: u64,
+    pub pass_threshold_bps: u16,
+    /// Set by `finalize_poll` on yes/no polls (those with a pass threshold).
+    pub outcome: Option<PollOutcome>,
+    pub max_choices: u8,
+    pub registration_start: Option<u64>,
+    /// Candidates that have not been removed. `poll_option_index` is the
+    /// next index to assign and also counts removed candidates.
+    pub candidate_count: u64,
+    /// Credits per voter in a quadratic poll, scaled by allowlist weight.
+    pub credit_budget: u64,
+    pub has_proposal: bool,
+    /// Mints accepted by `vote_with_nft`; empty unless the poll is NFT-gated.
+    #[max_len(MAX_GATE_MINTS)]
+    pub gate_mints: Vec<Pubkey>,
+}
+
+impl PollAccount {
+    pub fn require_voting_open(&self, current_time: i64) -> Result<()> {
+        if self.status == PollStatus::Cancelled {
+            return Err(ErrorCode::PollCancelled.into());
+        }
+
+        if current_time > (self.poll_voting_end as i64) {
+            return Err(ErrorCode::VotingEnded.into());
+        }
+
+        if current_time <= (self.poll_voting_start as i64) {
+            return Err(ErrorCode::VotingNotStarted.into());
+        }
+
+        Ok(())
+    }
+
+    /// Outcome of a yes/no poll where `approvals` of the `summed` votes went to
+    /// candidate 0. A poll with no votes never meets quorum.
+    pub fn governance_outcome(&self, approvals: u64, summed: u64) -> PollOutcome {
+        if summed == 0 || summed < self.quorum {
+            PollOutcome::QuorumNotMet
+        } else if approvals as u128 * 10_000 >= self.pass_threshold_bps as u128 * summed as u128 {
+            PollOutcome::Passed
+        } else {
+            PollOutcome::Failed
+        }
+    }
+
+    /// Adds counted ballots and their combined weight to the poll's turnout.
+    pub fn record_ballots(&mut self, ballots: u64, weight: u64) {
+        self.total_ballots += ballots;
+        self.total_votes += weight;
+    }
+
+    /// When the result can no longer change: the end of the reveal window for
+    /// secret ballots, otherwise the end of voting.
+    pub fn results_final_at(&self) -> u64 {
+        self.poll_reveal_end.unwrap_or(self.poll_voting_end)
+    }
+
+    /// Vote weight of `voter`: 1 on open polls, or the weight proven against
+    /// the allowlist root on restricted polls.
+    pub fn eligible_weight(
+        &self,
+        voter: &Pubkey,
+        eligibility: Option<&EligibilityProof>,
+    ) -> Result<u64> {
+        let Some(root) = self.eligibility_root else {
+            return Ok(1);
+        };
+
+        let Some(eligibility) = eligibility else {
+            return Err(ErrorCode::NotEligible.into());
+        };
+
+        let leaf = eligibility_leaf(voter, eligibility.weight);
+        if !verify_merkle_proof(&eligibility.proof, &root, leaf) {
+            return Err(ErrorCode::NotEligible.into());
+        }
+
+        if eligibility.weight == 0 {
+            return Err(ErrorCode::NoVotingPower.into());
+        }
+
+        Ok(eligibility.weight)
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct VoterReceipt {
+    pub poll_id: u64,
+    pub voter: Pubkey,
+    /// Candidate chosen on a single-choice ballot, once it is counted.
+    /// `None` on a delegator's receipt, which follows `delegated_to`'s vote.
+    pub candidate_index: Option<u64>,
+    pub weight: u64,
+    /// Candidate indices of a ranked ballot, in order of preference, or of
+    /// the candidates selected on an approval ballot.
+    #[max_len(MAX_CHOICES)]
+    pub choices: Vec<u16>,
+    /// Quadratic polls only: votes bought for each candidate in `choices`,
+    /// the voter's credit budget and the credits spent so far.
+    #[max_len(MAX_CHOICES)]
+    pub allocations: Vec<u64>,
+    pub credit_budget: u64,
+    pub credits_spent: u64,
+    pub rounds_tallied: u8,
+    /// Secret-ballot polls only: the sealed vote and whether it was opened.
+    pub commitment: Option<[u8; 32]>,
+    pub revealed: bool,
+    /// Set when the vote was cast by this delegate rather than the voter.
+    pub delegated_to: Option<Pubkey>,
+    pub bump: u8,
+}
+
+/// Tokens of a gate mint escrowed by `vote_with_nft` from one token
+/// account, at `["nft_vote", poll_id, mint, token_account]`. Closed by
+/// `unlock_nft`.
+#[account]
+#[derive(InitSpace)]
+pub struct NftVote {
+    pub poll_id: u64,
+    pub mint: Pubkey,
+    /// Token account the tokens were escrowed from.
+    pub token_account: Pubkey,
+    pub voter: Pubkey,
+    pub candidate_index: u64,
+    /// Tokens escrowed in the poll's account for the mint, each counted as
+    /// a vote.
+    pub votes: u64,
+    pub bump: u8,
+}
+
+/// Left behind by `close_poll` so the poll's id is never initialized again.
+#[account]
+#[derive(InitSpace)]
+pub struct ClosedPoll {
+    pub poll_id: u64,
+}
+
+/// Replay protection for relayed ballots: the next nonce `voter` must sign.
+/// Shared by all polls.
+#[account]
+#[derive(InitSpace)]
+pub struct RelayNonce {
+    pub voter: Pubkey,
+    pub nonce: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Delegation {
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    /// `None` for a delegation that applies to every poll.
+    pub poll_id: Option<u64>,
+    pub bump: u8,
+}
+
+/// Instructions a yes/no poll will run if it passes.
+#[account]
+pub struct Proposal {
+    pub poll_id: u64,
+    pub instructions: Vec<ProposalInstruction>,
+    /// Seconds to wait after the results are final before executing.
+    pub timelock: u64,
+    pub executed: bool,
+    pub bump: u8,
+}
+
+impl Proposal {
+    /// Account size, including the discriminator, for these instructions.
+    pub fn space(instructions: &[ProposalInstruction]) -> usize {
+        8 + 8
+            + 4
+            + instructions
+                .iter()
+                .map(|instruction| {
+                    32 + 4 + instruction.accounts.len() * 34 + 4 + instruction.data.len()
+                })
+                .sum::<usize>()
+            + 8
+            + 1
+            + 1
+    }
+}
+
+/// An instruction stored in a `Proposal`. The treasury PDA may be listed as
+/// a signer.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProposalInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<ProposalAccountMeta>,
+    pub data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProposalAccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct RoundTally {
+    pub poll_id: u64,
+    pub round: u8,
+    /// Votes per candidate index in this round.
+    #[max_len(MAX_RANKED_CANDIDATES)]
+    pub counts: Vec<u64>,
+    pub ballots_counted: u64,
+    pub exhausted: u64,
+    pub eliminated: Option<u8>,
+    pub bump: u8,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollCreated {
+    pub poll_id: u64,
+    pub authority: Pubkey,
+    pub ballot_mode: BallotMode,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateRegistered {
+    pub poll_id: u64,
+    pub candidate_index: u64,
+    pub candidate_name: String,
+}
+
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateRemoved {
+    pub poll_id: u64,
+    pub candidate_index: u64,
+}
+
+/// Emitted for every ballot that counts: direct, delegated, relayed,
+/// revealed, ranked, approval and NFT-gated votes. `choices` holds candidate indices — one for a
+/// plurality vote, the ranking for a ranked ballot, every selection for an
+/// approval ballot. A delegate's own event does not include the delegated
+/// votes; each delegator gets an event of their own.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteCast {
+    pub poll_id: u64,
+    pub voter: Pubkey,
+    pub choices: Vec<u64>,
+    pub weight: u64,
+}
+
+/// Emitted by `change_vote`. `weight` moves from the previous candidate to
+/// the new one.
+#[event]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteChanged {
+    pub poll_id: u64,
+    pub voter: Pubkey,
+    pub previous_candidate_index: u64,
+    pub candidate_index: u64,
+    pub weight: u64,
 }
 
 #[error_code]
@@ -139,4 +2770,268 @@ pub enum ErrorCode {
     VotingNotStarted,
     #[msg("Voting has ended")]
     VotingEnded,
+    #[msg("This wallet has already voted in this poll")]
+    AlreadyVoted,
+    #[msg("Only the poll authority can perform this action")]
+    Unauthorized,
+    #[msg("Voting has already started")]
+    VotingAlreadyStarted,
+    #[msg("Poll has been cancelled")]
+    PollCancelled,
+    #[msg("Voting start must be before voting end")]
+    InvalidVotingWindow,
+    #[msg("Governance mint does not match the poll")]
+    InvalidGovernanceMint,
+    #[msg("Token-weighted polls require the governance mint, token accounts and token program")]
+    MissingGovernanceAccounts,
+    #[msg("Voter has no voting power")]
+    NoVotingPower,
+    #[msg("Tokens stay locked until the poll ends")]
+    TokensLocked,
+    #[msg("This poll does not accept this kind of ballot")]
+    WrongBallotMode,
+    #[msg("These poll options cannot be combined")]
+    UnsupportedPollConfig,
+    #[msg("Poll has the maximum number of candidates")]
+    TooManyCandidates,
+    #[msg("Rankings must list distinct, existing candidate indices")]
+    InvalidRanking,
+    #[msg("Voting has not ended yet")]
+    VotingNotEnded,
+    #[msg("Tally is already complete")]
+    TallyComplete,
+    #[msg("Ballot does not belong to this poll")]
+    InvalidBallot,
+    #[msg("Ballot has already been counted in this round")]
+    BallotAlreadyTallied,
+    #[msg("Reveal window has ended")]
+    RevealEnded,
+    #[msg("No committed vote to reveal")]
+    NoCommitment,
+    #[msg("Vote has already been revealed")]
+    AlreadyRevealed,
+    #[msg("Revealed vote does not match the commitment")]
+    CommitmentMismatch,
+    #[msg("Signer is not on the poll's voter allowlist")]
+    NotEligible,
+    #[msg("Poll has already been finalized")]
+    PollFinalized,
+    #[msg("Instant-runoff tally has not finished")]
+    TallyIncomplete,
+    #[msg("Every candidate account of the poll must be provided")]
+    MissingCandidates,
+    #[msg("Account does not belong to this poll")]
+    InvalidCandidateAccount,
+    #[msg("Poll must be finalized or cancelled first")]
+    PollNotFinalized,
+    #[msg("Voters still have tokens locked in this poll")]
+    TokensStillLocked,
+    #[msg("Ballot must select between one and the poll's maximum number of candidates")]
+    InvalidChoices,
+    #[msg("Ballot selects the same candidate twice")]
+    DuplicateChoice,
+    #[msg("Delegation account is not valid for this vote")]
+    InvalidDelegation,
+    #[msg("Delegated votes are not supported in weighted or allowlisted polls")]
+    DelegationNotSupported,
+    #[msg("Quorum and pass threshold need a plurality poll and a threshold of at most 10000 bps")]
+    InvalidThreshold,
+    #[msg("Candidate registration has not opened yet")]
+    RegistrationNotOpen,
+    #[msg("Candidate registration has closed")]
+    RegistrationClosed,
+    #[msg("Signer has no single-choice vote to change")]
+    NoVoteToChange,
+    #[msg("Vote already goes to this candidate")]
+    VoteUnchanged,
+    #[msg("Vote count must be 1, or at least 1 in a quadratic poll")]
+    InvalidVoteCount,
+    #[msg("Quadratic polls need a credit budget and other polls must not set one")]
+    InvalidCreditBudget,
+    #[msg("Not enough voting credits left")]
+    InsufficientCredits,
+    #[msg("Proposals need a yes/no poll with a pass threshold")]
+    NotYesNoPoll,
+    #[msg("Proposal must contain at least one instruction")]
+    InvalidProposal,
+    #[msg("Poll has not passed")]
+    ProposalNotPassed,
+    #[msg("Proposal has already been executed")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal timelock has not elapsed")]
+    TimelockActive,
+    #[msg("A passed proposal must be executed before the poll is closed")]
+    ProposalNotExecuted,
+    #[msg("The poll's proposal account must be provided")]
+    MissingProposal,
+    #[msg("Gate mints must be distinct and no more than 16")]
+    InvalidGateMints,
+    #[msg("Token account is not the signer's account of one of the poll's gate mints")]
+    InvalidNftAccount,
+    #[msg("This token account has already voted in this poll")]
+    NftAlreadyUsed,
+    #[msg("relay_vote must follow an Ed25519 instruction verifying the ballot")]
+    MissingRelaySignature,
+    #[msg("Signed ballot does not match this vote")]
+    InvalidRelaySignature,
+    #[msg("Ballot nonce is not the voter's next relay nonce")]
+    InvalidNonce,
+    #[msg("This poll id belonged to a closed poll and cannot be reused")]
+    PollIdRetired,
+    #[msg("Ballot weights overflow the round's counts")]
+    TallyOverflow,
+    #[msg("Threshold polls need exactly two candidates, with yes at index 0")]
+    NotYesNoCandidates,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::sysvar::instructions::BorrowedInstruction;
+    use solana_instructions_sysvar::{construct_instructions_data, store_current_index_checked};
+
+    const HEADER_LEN: usize = 16;
+
+    /// Ed25519 program data verifying one signature whose public key,
+    /// signature and message all live in the instruction itself.
+    fn ed25519_data(signer: &Pubkey, message: &[u8]) -> Vec<u8> {
+        let public_key_offset = HEADER_LEN as u16;
+        let signature_offset = public_key_offset + 32;
+        let message_offset = signature_offset + 64;
+        let mut data = vec![1, 0];
+        for offset in [
+            signature_offset,
+            u16::MAX,
+            public_key_offset,
+            u16::MAX,
+            message_offset,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[7; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    /// Serialized instructions sysvar for a transaction of `[previous, relay_vote]`
+    /// with `relay_vote` as the instruction being executed.
+    fn sysvar_data(program_id: &Pubkey, previous: &[u8], current_index: u16) -> Vec<u8> {
+        let relay = [0u8; 8];
+        let mut data = construct_instructions_data(&[
+            BorrowedInstruction {
+                program_id,
+                accounts: vec![],
+                data: previous,
+            },
+            BorrowedInstruction {
+                program_id: &crate::ID,
+                accounts: vec![],
+                data: &relay,
+            },
+        ]);
+        store_current_index_checked(&mut data, current_index).unwrap();
+        data
+    }
+
+    fn verify(data: &mut [u8]) -> Result<(Pubkey, Vec<u8>)> {
+        let key = instructions_sysvar::ID;
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, data, &owner, false);
+        verified_ed25519_message(&info)
+    }
+
+    #[test]
+    fn charges_the_difference_of_squares_for_quadratic_votes() {
+        assert_eq!(quadratic_credits(0, 0, 2), Some(4));
+        assert_eq!(quadratic_credits(4, 2, 1), Some(9));
+    }
+
+    #[test]
+    fn rejects_quadratic_allocations_that_overflow() {
+        assert_eq!(quadratic_credits(0, 0, u64::MAX), None);
+        assert_eq!(quadratic_credits(0, u64::MAX, 1), None);
+        assert_eq!(quadratic_credits(0, 0, 1 << 32), None);
+        assert_eq!(quadratic_credits(u64::MAX, 0, 1), None);
+        assert_eq!(
+            quadratic_credits(0, 0, u32::MAX as u64),
+            Some((u32::MAX as u64).pow(2))
+        );
+    }
+
+    #[test]
+    fn reads_signer_and_message_from_previous_instruction() {
+        let signer = Pubkey::new_unique();
+        let message = relay_ballot_message(3, 1, 0);
+        let mut data = sysvar_data(&ED25519_PROGRAM_ID, &ed25519_data(&signer, &message), 1);
+
+        assert_eq!(verify(&mut data).unwrap(), (signer, message));
+    }
+
+    #[test]
+    fn rejects_relay_vote_as_first_instruction() {
+        let message = relay_ballot_message(3, 1, 0);
+        let ed25519 = ed25519_data(&Pubkey::new_unique(), &message);
+        let mut data = sysvar_data(&ED25519_PROGRAM_ID, &ed25519, 0);
+
+        assert_eq!(
+            verify(&mut data).unwrap_err(),
+            ErrorCode::MissingRelaySignature.into()
+        );
+    }
+
+    #[test]
+    fn rejects_previous_instruction_from_another_program() {
+        let message = relay_ballot_message(3, 1, 0);
+        let ed25519 = ed25519_data(&Pubkey::new_unique(), &message);
+        let mut data = sysvar_data(&Pubkey::new_unique(), &ed25519, 1);
+
+        assert_eq!(
+            verify(&mut data).unwrap_err(),
+            ErrorCode::MissingRelaySignature.into()
+        );
+    }
+
+    #[test]
+    fn rejects_offsets_into_other_instructions() {
+        let message = relay_ballot_message(3, 1, 0);
+        let mut ed25519 = ed25519_data(&Pubkey::new_unique(), &message);
+        // Point the message at instruction 1 instead of the Ed25519 instruction.
+        ed25519[14..16].copy_from_slice(&1u16.to_le_bytes());
+        let mut data = sysvar_data(&ED25519_PROGRAM_ID, &ed25519, 1);
+
+        assert_eq!(
+            verify(&mut data).unwrap_err(),
+            ErrorCode::InvalidRelaySignature.into()
+        );
+    }
+
+    #[test]
+    fn rejects_multiple_signatures() {
+        let message = relay_ballot_message(3, 1, 0);
+        let mut ed25519 = ed25519_data(&Pubkey::new_unique(), &message);
+        ed25519[0] = 2;
+        let mut data = sysvar_data(&ED25519_PROGRAM_ID, &ed25519, 1);
+
+        assert_eq!(
+            verify(&mut data).unwrap_err(),
+            ErrorCode::InvalidRelaySignature.into()
+        );
+    }
+
+    #[test]
+    fn rejects_account_that_is_not_the_sysvar() {
+        let message = relay_ballot_message(3, 1, 0);
+        let ed25519 = ed25519_data(&Pubkey::new_unique(), &message);
+        let mut data = sysvar_data(&ED25519_PROGRAM_ID, &ed25519, 1);
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false);
+
+        assert!(verified_ed25519_message(&info).is_err());
+    }
 }
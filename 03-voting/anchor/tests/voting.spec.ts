@@ -1,6 +1,7 @@
 import * as anchor from "@anchor-lang/core";
-import { BN, Program } from "@anchor-lang/core";
-import { PublicKey } from "@solana/web3.js";
+import { BN, EventParser, Program } from "@anchor-lang/core";
+import { createHash } from "crypto";
+import { Ed25519Program, Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram, Transaction } from "@solana/web3.js";
 import { Voting } from "../target/types/voting";
 
 const PROGRAM_ID = new PublicKey("65KHV8cXwJ8apTKMqnpSdhdHkHhRySatgKMwnxm6C3gG");
@@ -17,6 +18,113 @@ describe("voting", () => {
     PROGRAM_ID
   );
 
+  const fundedKeypair = async () => {
+    const keypair = Keypair.generate();
+    const signature = await program.provider.connection.requestAirdrop(
+      keypair.publicKey,
+      LAMPORTS_PER_SOL
+    );
+    await program.provider.connection.confirmTransaction(signature);
+    return keypair;
+  };
+
+  const pollSettings = (overrides: Record<string, unknown> = {}): any => ({
+    ballotMode: { plurality: {} },
+    revealEnd: null,
+    eligibilityRoot: null,
+    quorum: new BN(0),
+    passThresholdBps: 0,
+    maxChoices: 1,
+    registrationStart: null,
+    creditBudget: new BN(0),
+    gateMints: [],
+    ...overrides,
+  });
+
+  const candidateAddress = (pollId: BN, index: number) =>
+    PublicKey.findProgramAddressSync(
+      [pollId.toArrayLike(Buffer, "le", 8), new BN(index).toArrayLike(Buffer, "le", 8)],
+      PROGRAM_ID
+    )[0];
+
+  const sha256 = (...parts: Buffer[]) => {
+    const hash = createHash("sha256");
+    parts.forEach((part) => hash.update(part));
+    return hash.digest();
+  };
+
+  const eligibilityLeaf = (voter: PublicKey, weight: number) =>
+    sha256(Buffer.from([0]), voter.toBuffer(), new BN(weight).toArrayLike(Buffer, "le", 8));
+
+  const merkleParent = (a: Buffer, b: Buffer) =>
+    Buffer.compare(a, b) <= 0
+      ? sha256(Buffer.from([1]), a, b)
+      : sha256(Buffer.from([1]), b, a);
+
+  const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  // Plain SPL Token instructions: InitializeMint2, InitializeAccount3, MintTo, Transfer.
+  const TOKEN_PROGRAM_ID = new PublicKey("TokenkegQfeZyiNwAJbNbGhPfSWb6ZkeQpmAQEr9ELdsn");
+
+  const tokenInstruction = (keys: PublicKey[], data: Buffer, signerIndex = -1) => ({
+    programId: TOKEN_PROGRAM_ID,
+    keys: keys.map((pubkey, index) => ({ pubkey, isSigner: index === signerIndex, isWritable: index < 2 })),
+    data,
+  });
+
+  const createMint = async () => {
+    const connection = program.provider.connection;
+    const payer = program.provider.publicKey;
+    const mint = Keypair.generate();
+    await program.provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: payer,
+          newAccountPubkey: mint.publicKey,
+          lamports: await connection.getMinimumBalanceForRentExemption(82),
+          space: 82,
+          programId: TOKEN_PROGRAM_ID,
+        }),
+        tokenInstruction(
+          [mint.publicKey],
+          Buffer.concat([Buffer.from([20, 0]), payer.toBuffer(), Buffer.from([0])])
+        )
+      ),
+      [mint]
+    );
+    return mint.publicKey;
+  };
+
+  const createTokenAccount = async (mint: PublicKey, owner: PublicKey, amount = 0) => {
+    const connection = program.provider.connection;
+    const payer = program.provider.publicKey;
+    const account = Keypair.generate();
+    const transaction = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: payer,
+        newAccountPubkey: account.publicKey,
+        lamports: await connection.getMinimumBalanceForRentExemption(165),
+        space: 165,
+        programId: TOKEN_PROGRAM_ID,
+      }),
+      tokenInstruction([account.publicKey, mint], Buffer.concat([Buffer.from([18]), owner.toBuffer()]))
+    );
+    if (amount > 0) {
+      transaction.add(
+        tokenInstruction(
+          [mint, account.publicKey, payer],
+          Buffer.concat([Buffer.from([7]), new BN(amount).toArrayLike(Buffer, "le", 8)]),
+          2
+        )
+      );
+    }
+    await program.provider.sendAndConfirm(transaction, [account]);
+    return account.publicKey;
+  };
+
+  const tokenBalance = async (account: PublicKey) =>
+    Number((await program.provider.connection.getTokenAccountBalance(account)).value.amount);
+
   it("initializes a poll", async () => {
     await program.methods
       .initializePoll(
@@ -24,7 +132,8 @@ describe("voting", () => {
         new BN(0),
         new BN(1893456000), // 2030-01-01
         "Test Poll",
-        "A poll to test the voting program"
+        "A poll to test the voting program",
+        pollSettings()
       )
       .rpc();
 
@@ -36,6 +145,15 @@ describe("voting", () => {
     expect(pollAccount.pollVotingStart.toNumber()).toEqual(0);
     expect(pollAccount.pollVotingEnd.toNumber()).toEqual(1893456000);
     expect(pollAccount.pollOptionIndex.toNumber()).toEqual(0);
+    expect(pollAccount.authority.toBase58()).toEqual(program.provider.publicKey.toBase58());
+  });
+
+  it("rejects re-initializing an existing poll", async () => {
+    await expect(
+      program.methods
+        .initializePoll(POLL_ID, new BN(0), new BN(1893456000), "Hijacked", "Overwritten", pollSettings())
+        .rpc()
+    ).rejects.toThrow();
   });
 
   it("initializes candidates", async () => {
@@ -51,18 +169,911 @@ describe("voting", () => {
     expect(pollAccount.pollOptionIndex.toNumber()).toEqual(2);
   });
 
-  it("casts a vote", async () => {
-    const [aliceAddress] = PublicKey.findProgramAddressSync(
-      [POLL_ID.toArrayLike(Buffer, "le", 8), Buffer.from("Alice")],
+  it("rejects candidates from a wallet that is not the poll authority", async () => {
+    const outsider = await fundedKeypair();
+
+    await expect(
+      program.methods
+        .initializeCandidate(POLL_ID, "Mallory")
+        .accounts({ signer: outsider.publicKey })
+        .signers([outsider])
+        .rpc()
+    ).rejects.toThrow(/Unauthorized/);
+  });
+
+  it("updates a poll before voting opens and then cancels it", async () => {
+    const UPCOMING_POLL_ID = new BN(2);
+    const [upcomingPollAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("poll"), UPCOMING_POLL_ID.toArrayLike(Buffer, "le", 8)],
       PROGRAM_ID
     );
 
     await program.methods
-      .vote(POLL_ID, "Alice")
+      .initializePoll(UPCOMING_POLL_ID, new BN(1861920000), new BN(1893456000), "Draft", "Draft poll", pollSettings())
+      .rpc();
+
+    await program.methods
+      .updatePoll(UPCOMING_POLL_ID, new BN(1861920000), new BN(1893456000), "Final", "Final poll")
+      .rpc();
+
+    let pollAccount = await program.account.pollAccount.fetch(upcomingPollAddress);
+    expect(pollAccount.pollName).toEqual("Final");
+    expect(pollAccount.pollDescription).toEqual("Final poll");
+
+    await program.methods.cancelPoll(UPCOMING_POLL_ID).rpc();
+
+    pollAccount = await program.account.pollAccount.fetch(upcomingPollAddress);
+    expect(pollAccount.status).toEqual({ cancelled: {} });
+
+    await expect(
+      program.methods.initializeCandidate(UPCOMING_POLL_ID, "Carol").rpc()
+    ).rejects.toThrow(/PollCancelled/);
+  });
+
+  it("rejects updates once voting has started", async () => {
+    await expect(
+      program.methods
+        .updatePoll(POLL_ID, new BN(0), new BN(1893456000), "Renamed", "Too late")
+        .rpc()
+    ).rejects.toThrow(/VotingAlreadyStarted/);
+  });
+
+  it("casts a vote", async () => {
+    const aliceAddress = candidateAddress(POLL_ID, 0);
+
+    await program.methods
+      .vote(POLL_ID, new BN(0), new BN(1), null)
       .rpc();
 
     const aliceAccount = await program.account.candidateAccount.fetch(aliceAddress);
     console.log("Alice account:", aliceAccount);
     expect(aliceAccount.candidateVotes.toNumber()).toEqual(1);
+
+    const [receiptAddress] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("receipt"),
+        POLL_ID.toArrayLike(Buffer, "le", 8),
+        program.provider.publicKey.toBuffer(),
+      ],
+      PROGRAM_ID
+    );
+    const receipt = await program.account.voterReceipt.fetch(receiptAddress);
+    expect(receipt.voter.toBase58()).toEqual(program.provider.publicKey.toBase58());
+    expect(receipt.candidateIndex.toNumber()).toEqual(0);
+  });
+
+  it("rejects a second vote from the same wallet", async () => {
+    await expect(
+      program.methods.vote(POLL_ID, new BN(1), new BN(1), null).rpc()
+    ).rejects.toThrow(/AlreadyVoted/);
+  });
+
+  it("moves a vote to another candidate", async () => {
+    const aliceAddress = candidateAddress(POLL_ID, 0);
+    const bobAddress = candidateAddress(POLL_ID, 1);
+
+    await program.methods
+      .changeVote(POLL_ID, new BN(1))
+      .accountsPartial({ previousCandidate: aliceAddress })
+      .rpc();
+
+    const aliceAccount = await program.account.candidateAccount.fetch(aliceAddress);
+    const bobAccount = await program.account.candidateAccount.fetch(bobAddress);
+    expect(aliceAccount.candidateVotes.toNumber()).toEqual(0);
+    expect(bobAccount.candidateVotes.toNumber()).toEqual(1);
+
+    await expect(
+      program.methods
+        .changeVote(POLL_ID, new BN(1))
+        .accountsPartial({ previousCandidate: bobAddress })
+        .rpc()
+    ).rejects.toThrow(/VoteUnchanged/);
+  });
+
+  it("runs an instant-runoff tally for a ranked-choice poll", async () => {
+    const RANKED_POLL_ID = new BN(3);
+    const [rankedPollAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("poll"), RANKED_POLL_ID.toArrayLike(Buffer, "le", 8)],
+      PROGRAM_ID
+    );
+    const now = Math.floor(Date.now() / 1000);
+    const end = now + 20;
+
+    await program.methods
+      .initializePoll(RANKED_POLL_ID, new BN(now - 10), new BN(end), "Ranked", "Ranked poll", pollSettings({
+        ballotMode: { rankedChoice: {} },
+      }))
+      .rpc();
+    for (const name of ["Alice", "Bob", "Carol"]) {
+      await program.methods.initializeCandidate(RANKED_POLL_ID, name).rpc();
+    }
+
+    const ballots: [Keypair, number[]][] = [
+      [await fundedKeypair(), [0, 1]],
+      [await fundedKeypair(), [1, 0]],
+      [await fundedKeypair(), [2, 1]],
+    ];
+    const receipts: PublicKey[] = [];
+    for (const [voter, rankings] of ballots) {
+      await program.methods
+        .voteRanked(RANKED_POLL_ID, Buffer.from(rankings), null)
+        .accounts({ signer: voter.publicKey })
+        .signers([voter])
+        .rpc();
+      receipts.push(
+        PublicKey.findProgramAddressSync(
+          [
+            Buffer.from("receipt"),
+            RANKED_POLL_ID.toArrayLike(Buffer, "le", 8),
+            voter.publicKey.toBuffer(),
+          ],
+          PROGRAM_ID
+        )[0]
+      );
+    }
+
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    const remainingAccounts = receipts.map((pubkey) => ({
+      pubkey,
+      isSigner: false,
+      isWritable: true,
+    }));
+
+    // Round 0: everyone has one first preference, so Carol (highest index) is eliminated.
+    await program.methods.tallyRound(RANKED_POLL_ID).remainingAccounts(remainingAccounts).rpc();
+    // Round 1: Carol's ballot moves to Bob, who now holds a majority.
+    await program.methods.tallyRound(RANKED_POLL_ID).remainingAccounts(remainingAccounts).rpc();
+
+    const pollAccount = await program.account.pollAccount.fetch(rankedPollAddress);
+    expect(pollAccount.tallyComplete).toEqual(true);
+    expect(pollAccount.tallyWinner.toNumber()).toEqual(1);
+
+    const [roundZeroAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("round"), RANKED_POLL_ID.toArrayLike(Buffer, "le", 8), Buffer.from([0])],
+      PROGRAM_ID
+    );
+    const roundZero = await program.account.roundTally.fetch(roundZeroAddress);
+    expect(roundZero.counts.map((count) => count.toNumber())).toEqual([1, 1, 1]);
+    expect(roundZero.eliminated).toEqual(2);
+
+    await program.methods.finalizePoll(RANKED_POLL_ID).rpc();
+    const finalized = await program.account.pollAccount.fetch(rankedPollAddress);
+    expect(finalized.status).toEqual({ finalized: {} });
+    expect(finalized.winner.toNumber()).toEqual(1);
+  }, 60_000);
+
+  it("counts a secret ballot only once it is revealed", async () => {
+    const SECRET_POLL_ID = new BN(4);
+    const now = Math.floor(Date.now() / 1000);
+    const end = now + 15;
+
+    await program.methods
+      .initializePoll(SECRET_POLL_ID, new BN(now - 10), new BN(end), "Secret", "Secret poll", pollSettings({
+        revealEnd: new BN(end + 60),
+      }))
+      .rpc();
+    await program.methods.initializeCandidate(SECRET_POLL_ID, "Alice").rpc();
+
+    const salt = Buffer.alloc(32, 7);
+    const commitment = sha256(
+      new BN(0).toArrayLike(Buffer, "le", 8),
+      salt,
+      program.provider.publicKey.toBuffer()
+    );
+
+    await program.methods.commitVote(SECRET_POLL_ID, [...commitment], null).rpc();
+
+    const aliceAddress = candidateAddress(SECRET_POLL_ID, 0);
+    let aliceAccount = await program.account.candidateAccount.fetch(aliceAddress);
+    expect(aliceAccount.candidateVotes.toNumber()).toEqual(0);
+
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    await program.methods.revealVote(SECRET_POLL_ID, new BN(0), [...salt]).rpc();
+
+    aliceAccount = await program.account.candidateAccount.fetch(aliceAddress);
+    expect(aliceAccount.candidateVotes.toNumber()).toEqual(1);
+  }, 60_000);
+
+  it("only lets allowlisted wallets vote, with their allowlist weight", async () => {
+    const ALLOWLIST_POLL_ID = new BN(5);
+    const member = await fundedKeypair();
+    const outsider = await fundedKeypair();
+
+    const walletLeaf = eligibilityLeaf(program.provider.publicKey, 3);
+    const memberLeaf = eligibilityLeaf(member.publicKey, 1);
+    const root = merkleParent(walletLeaf, memberLeaf);
+
+    await program.methods
+      .initializePoll(ALLOWLIST_POLL_ID, new BN(0), new BN(1893456000), "Members", "Members only", pollSettings({
+        eligibilityRoot: [...root],
+      }))
+      .rpc();
+    await program.methods.initializeCandidate(ALLOWLIST_POLL_ID, "Alice").rpc();
+
+    await program.methods
+      .vote(ALLOWLIST_POLL_ID, new BN(0), new BN(1), { weight: new BN(3), proof: [[...memberLeaf]] })
+      .rpc();
+
+    const aliceAddress = candidateAddress(ALLOWLIST_POLL_ID, 0);
+    const aliceAccount = await program.account.candidateAccount.fetch(aliceAddress);
+    expect(aliceAccount.candidateVotes.toNumber()).toEqual(3);
+
+    await expect(
+      program.methods
+        .vote(ALLOWLIST_POLL_ID, new BN(0), new BN(1), { weight: new BN(1), proof: [[...walletLeaf]] })
+        .accounts({ signer: outsider.publicKey })
+        .signers([outsider])
+        .rpc()
+    ).rejects.toThrow(/NotEligible/);
+  });
+
+  it("finalizes a poll and closes it, refunding rent to the authority", async () => {
+    const CLOSING_POLL_ID = new BN(6);
+    const [closingPollAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("poll"), CLOSING_POLL_ID.toArrayLike(Buffer, "le", 8)],
+      PROGRAM_ID
+    );
+    const now = Math.floor(Date.now() / 1000);
+    const end = now + 15;
+
+    await program.methods
+      .initializePoll(CLOSING_POLL_ID, new BN(now - 10), new BN(end), "Closing", "Closing poll", pollSettings())
+      .rpc();
+    const candidates = [];
+    for (const name of ["Alice", "Bob"]) {
+      await program.methods.initializeCandidate(CLOSING_POLL_ID, name).rpc();
+      candidates.push({
+        pubkey: candidateAddress(CLOSING_POLL_ID, candidates.length),
+        isSigner: false,
+        isWritable: true,
+      });
+    }
+    await program.methods.vote(CLOSING_POLL_ID, new BN(0), new BN(1), null).rpc();
+
+    await expect(
+      program.methods.finalizePoll(CLOSING_POLL_ID).remainingAccounts(candidates).rpc()
+    ).rejects.toThrow(/VotingNotEnded/);
+
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    await program.methods.finalizePoll(CLOSING_POLL_ID).remainingAccounts(candidates).rpc();
+    const pollAccount = await program.account.pollAccount.fetch(closingPollAddress);
+    expect(pollAccount.winner.toNumber()).toEqual(0);
+    expect(pollAccount.totalVotes.toNumber()).toEqual(1);
+    expect(pollAccount.isTie).toEqual(false);
+
+    await program.methods.closePoll(CLOSING_POLL_ID).remainingAccounts(candidates).rpc();
+    expect(await program.provider.connection.getAccountInfo(closingPollAddress)).toBeNull();
+    expect(await program.provider.connection.getAccountInfo(candidates[0].pubkey)).toBeNull();
+
+    // The id stays retired, so old receipts and treasury seeds can't be reused.
+    await expect(
+      program.methods
+        .initializePoll(CLOSING_POLL_ID, new BN(0), new BN(1893456000), "Reopened", "Same id", pollSettings())
+        .rpc()
+    ).rejects.toThrow(/PollIdRetired/);
+  }, 60_000);
+
+  it("fails a yes/no poll that misses its supermajority", async () => {
+    const GOVERNANCE_POLL_ID = new BN(7);
+    const [governancePollAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("poll"), GOVERNANCE_POLL_ID.toArrayLike(Buffer, "le", 8)],
+      PROGRAM_ID
+    );
+    const now = Math.floor(Date.now() / 1000);
+    const end = now + 15;
+
+    await program.methods
+      .initializePoll(GOVERNANCE_POLL_ID, new BN(now - 10), new BN(end), "Upgrade", "Approve the upgrade?", pollSettings({
+        quorum: new BN(2),
+        passThresholdBps: 6000,
+      }))
+      .rpc();
+    const candidates = [];
+    for (const name of ["Yes", "No"]) {
+      await program.methods.initializeCandidate(GOVERNANCE_POLL_ID, name).rpc();
+      candidates.push({
+        pubkey: candidateAddress(GOVERNANCE_POLL_ID, candidates.length),
+        isSigner: false,
+        isWritable: false,
+      });
+    }
+
+    const voter = await fundedKeypair();
+    await program.methods.vote(GOVERNANCE_POLL_ID, new BN(0), new BN(1), null).rpc();
+    await program.methods
+      .vote(GOVERNANCE_POLL_ID, new BN(1), new BN(1), null)
+      .accounts({ signer: voter.publicKey })
+      .signers([voter])
+      .rpc();
+
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    await program.methods.finalizePoll(GOVERNANCE_POLL_ID).remainingAccounts(candidates).rpc();
+    const pollAccount = await program.account.pollAccount.fetch(governancePollAddress);
+    expect(pollAccount.isTie).toEqual(true);
+    expect(pollAccount.outcome).toEqual({ failed: {} });
+  }, 60_000);
+
+  it("counts delegated votes exactly once", async () => {
+    const DELEGATED_POLL_ID = new BN(8);
+    const pollIdBytes = DELEGATED_POLL_ID.toArrayLike(Buffer, "le", 8);
+    const delegator = await fundedKeypair();
+    const delegate = await fundedKeypair();
+
+    await program.methods
+      .initializePoll(DELEGATED_POLL_ID, new BN(0), new BN(1893456000), "Delegated", "Delegated poll", pollSettings())
+      .rpc();
+    await program.methods.initializeCandidate(DELEGATED_POLL_ID, "Alice").rpc();
+
+    await program.methods
+      .delegate(DELEGATED_POLL_ID, delegate.publicKey)
+      .accounts({ signer: delegator.publicKey })
+      .signers([delegator])
+      .rpc();
+
+    const [delegationAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("delegation"), delegator.publicKey.toBuffer(), pollIdBytes],
+      PROGRAM_ID
+    );
+    const [delegatorReceipt] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), pollIdBytes, delegator.publicKey.toBuffer()],
+      PROGRAM_ID
+    );
+    const delegationAccounts = [
+      { pubkey: delegationAddress, isSigner: false, isWritable: false },
+      { pubkey: delegatorReceipt, isSigner: false, isWritable: true },
+    ];
+
+    await program.methods
+      .vote(DELEGATED_POLL_ID, new BN(0), new BN(1), null)
+      .accounts({ signer: delegate.publicKey })
+      .remainingAccounts(delegationAccounts)
+      .signers([delegate])
+      .rpc();
+
+    const aliceAddress = candidateAddress(DELEGATED_POLL_ID, 0);
+    const aliceAccount = await program.account.candidateAccount.fetch(aliceAddress);
+    expect(aliceAccount.candidateVotes.toNumber()).toEqual(2);
+
+    const receipt = await program.account.voterReceipt.fetch(delegatorReceipt);
+    expect(receipt.delegatedTo.toBase58()).toEqual(delegate.publicKey.toBase58());
+
+    await expect(
+      program.methods
+        .vote(DELEGATED_POLL_ID, new BN(0), new BN(1), null)
+        .accounts({ signer: delegator.publicKey })
+        .signers([delegator])
+        .rpc()
+    ).rejects.toThrow(/AlreadyVoted/);
+  });
+
+  it("casts an approval ballot for several candidates", async () => {
+    const APPROVAL_POLL_ID = new BN(9);
+    const candidateMetas = (indices: number[]) =>
+      indices.map((index) => ({
+        pubkey: candidateAddress(APPROVAL_POLL_ID, index),
+        isSigner: false,
+        isWritable: true,
+      }));
+
+    await program.methods
+      .initializePoll(APPROVAL_POLL_ID, new BN(0), new BN(1893456000), "Approval", "Pick up to two", pollSettings({
+        maxChoices: 2,
+      }))
+      .rpc();
+    for (const name of ["Alice", "Bob", "Carol"]) {
+      await program.methods.initializeCandidate(APPROVAL_POLL_ID, name).rpc();
+    }
+
+    await program.methods
+      .voteMany(APPROVAL_POLL_ID, [new BN(0), new BN(2)], null)
+      .remainingAccounts(candidateMetas([0, 2]))
+      .rpc();
+
+    const votes = await Promise.all(
+      [0, 1, 2].map(async (index) =>
+        (await program.account.candidateAccount.fetch(candidateAddress(APPROVAL_POLL_ID, index)))
+          .candidateVotes.toNumber()
+      )
+    );
+    expect(votes).toEqual([1, 0, 1]);
+
+    await expect(
+      program.methods
+        .voteMany(APPROVAL_POLL_ID, [new BN(1)], null)
+        .remainingAccounts(candidateMetas([1]))
+        .rpc()
+    ).rejects.toThrow(/AlreadyVoted/);
+
+    const voter = await fundedKeypair();
+    await expect(
+      program.methods
+        .voteMany(APPROVAL_POLL_ID, [new BN(0), new BN(1), new BN(2)], null)
+        .accounts({ signer: voter.publicKey })
+        .remainingAccounts(candidateMetas([0, 1, 2]))
+        .signers([voter])
+        .rpc()
+    ).rejects.toThrow(/InvalidChoices/);
+  });
+
+  it("emits events that rebuild the tally", async () => {
+    const EVENT_POLL_ID = new BN(10);
+    const events: { name: string; data: any }[] = [];
+    const parser = new EventParser(program.programId, program.coder);
+    const record = async (signature: string) => {
+      await program.provider.connection.confirmTransaction(signature, "confirmed");
+      const tx = await program.provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      events.push(...parser.parseLogs(tx.meta.logMessages));
+    };
+
+    await record(
+      await program.methods
+        .initializePoll(EVENT_POLL_ID, new BN(0), new BN(1893456000), "Events", "Indexed poll", pollSettings())
+        .rpc()
+    );
+    await record(await program.methods.initializeCandidate(EVENT_POLL_ID, "Alice").rpc());
+    await record(await program.methods.initializeCandidate(EVENT_POLL_ID, "Bob").rpc());
+    await record(await program.methods.vote(EVENT_POLL_ID, new BN(1), new BN(1), null).rpc());
+
+    expect(events.map((event) => event.name)).toEqual([
+      "pollCreated",
+      "candidateRegistered",
+      "candidateRegistered",
+      "voteCast",
+    ]);
+    expect(events[2].data.candidateName).toEqual("Bob");
+    expect(events[3].data.voter.toBase58()).toEqual(program.provider.publicKey.toBase58());
+    expect(events[3].data.choices.map((index: BN) => index.toNumber())).toEqual([1]);
+    expect(events[3].data.weight.toNumber()).toEqual(1);
+  });
+
+  it("registers and removes candidates only before voting opens", async () => {
+    const REGISTRATION_POLL_ID = new BN(11);
+    const [registrationPollAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("poll"), REGISTRATION_POLL_ID.toArrayLike(Buffer, "le", 8)],
+      PROGRAM_ID
+    );
+    const now = Math.floor(Date.now() / 1000);
+    const start = now + 10;
+
+    await program.methods
+      .initializePoll(REGISTRATION_POLL_ID, new BN(start), new BN(start + 60), "Registration", "Registration window", pollSettings({
+        registrationStart: new BN(now - 10),
+      }))
+      .rpc();
+    for (const name of ["Alice", "Bob", "A candidate whose name is longer than thirty-two bytes"]) {
+      await program.methods.initializeCandidate(REGISTRATION_POLL_ID, name).rpc();
+    }
+
+    await program.methods.removeCandidate(REGISTRATION_POLL_ID, new BN(1)).rpc();
+
+    const pollAccount = await program.account.pollAccount.fetch(registrationPollAddress);
+    expect(pollAccount.pollOptionIndex.toNumber()).toEqual(3);
+    expect(pollAccount.candidateCount.toNumber()).toEqual(2);
+    expect(
+      await program.provider.connection.getAccountInfo(candidateAddress(REGISTRATION_POLL_ID, 1))
+    ).toBeNull();
+
+    await sleep((start - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    await expect(
+      program.methods.initializeCandidate(REGISTRATION_POLL_ID, "Carol").rpc()
+    ).rejects.toThrow(/RegistrationClosed/);
+    await expect(
+      program.methods.removeCandidate(REGISTRATION_POLL_ID, new BN(0)).rpc()
+    ).rejects.toThrow(/VotingAlreadyStarted/);
+  }, 60_000);
+
+  it("charges quadratic credits for repeated votes", async () => {
+    const QUADRATIC_POLL_ID = new BN(12);
+    const [receiptAddress] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("receipt"),
+        QUADRATIC_POLL_ID.toArrayLike(Buffer, "le", 8),
+        program.provider.publicKey.toBuffer(),
+      ],
+      PROGRAM_ID
+    );
+
+    await program.methods
+      .initializePoll(QUADRATIC_POLL_ID, new BN(0), new BN(1893456000), "Quadratic", "Signal poll", pollSettings({
+        ballotMode: { quadratic: {} },
+        creditBudget: new BN(10),
+      }))
+      .rpc();
+    for (const name of ["Alice", "Bob"]) {
+      await program.methods.initializeCandidate(QUADRATIC_POLL_ID, name).rpc();
+    }
+
+    // 2 votes for Alice cost 4 credits, a third costs 9 - 4 = 5 more.
+    await program.methods.vote(QUADRATIC_POLL_ID, new BN(0), new BN(2), null).rpc();
+    await program.methods.vote(QUADRATIC_POLL_ID, new BN(0), new BN(1), null).rpc();
+    // A vote for Bob costs 1, using up the last credit.
+    await program.methods.vote(QUADRATIC_POLL_ID, new BN(1), new BN(1), null).rpc();
+
+    const receipt = await program.account.voterReceipt.fetch(receiptAddress);
+    expect(receipt.creditsSpent.toNumber()).toEqual(10);
+    expect(receipt.allocations.map((votes) => votes.toNumber())).toEqual([3, 1]);
+
+    const alice = await program.account.candidateAccount.fetch(candidateAddress(QUADRATIC_POLL_ID, 0));
+    expect(alice.candidateVotes.toNumber()).toEqual(3);
+
+    await expect(
+      program.methods.vote(QUADRATIC_POLL_ID, new BN(1), new BN(1), null).rpc()
+    ).rejects.toThrow(/InsufficientCredits/);
+
+    // The cost of u64::MAX votes overflows instead of wrapping below the budget.
+    const whale = await fundedKeypair();
+    await expect(
+      program.methods
+        .vote(QUADRATIC_POLL_ID, new BN(0), new BN("18446744073709551615"), null)
+        .accounts({ signer: whale.publicKey })
+        .signers([whale])
+        .rpc()
+    ).rejects.toThrow(/InsufficientCredits/);
+  });
+
+  it("executes a passed proposal from the poll treasury", async () => {
+    const PROPOSAL_POLL_ID = new BN(13);
+    const pollIdBytes = PROPOSAL_POLL_ID.toArrayLike(Buffer, "le", 8);
+    const [treasury] = PublicKey.findProgramAddressSync([Buffer.from("treasury"), pollIdBytes], PROGRAM_ID);
+    const recipient = Keypair.generate();
+    const now = Math.floor(Date.now() / 1000);
+    const start = now + 10;
+    const end = start + 10;
+
+    await program.methods
+      .initializePoll(PROPOSAL_POLL_ID, new BN(start), new BN(end), "Grant", "Fund the grant?", pollSettings({
+        passThresholdBps: 5000,
+      }))
+      .rpc();
+    for (const name of ["Yes", "No", "Abstain"]) {
+      await program.methods.initializeCandidate(PROPOSAL_POLL_ID, name).rpc();
+    }
+
+    const grant = SystemProgram.transfer({
+      fromPubkey: treasury,
+      toPubkey: recipient.publicKey,
+      lamports: LAMPORTS_PER_SOL / 100,
+    });
+    const createProposal = () =>
+      program.methods
+        .createProposal(
+          PROPOSAL_POLL_ID,
+          [{ programId: grant.programId, accounts: grant.keys, data: grant.data }],
+          new BN(0)
+        )
+        .accountsPartial({ yesCandidate: candidateAddress(PROPOSAL_POLL_ID, 0) })
+        .rpc();
+
+    // Proposals only run on a plain yes/no choice.
+    await expect(createProposal()).rejects.toThrow(/NotYesNoCandidates/);
+    await program.methods.removeCandidate(PROPOSAL_POLL_ID, new BN(2)).rpc();
+    await createProposal();
+    await expect(
+      program.methods.initializeCandidate(PROPOSAL_POLL_ID, "Abstain").rpc()
+    ).rejects.toThrow(/NotYesNoCandidates/);
+    await program.provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: program.provider.publicKey,
+          toPubkey: treasury,
+          lamports: LAMPORTS_PER_SOL / 10,
+        })
+      )
+    );
+
+    await sleep((start - Math.floor(Date.now() / 1000) + 2) * 1000);
+    await program.methods.vote(PROPOSAL_POLL_ID, new BN(0), new BN(1), null).rpc();
+    // The vote on the proposal can no longer be called off.
+    await expect(program.methods.cancelPoll(PROPOSAL_POLL_ID).rpc()).rejects.toThrow(/VotingAlreadyStarted/);
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    await program.methods
+      .finalizePoll(PROPOSAL_POLL_ID)
+      .remainingAccounts(
+        [0, 1].map((index) => ({
+          pubkey: candidateAddress(PROPOSAL_POLL_ID, index),
+          isSigner: false,
+          isWritable: false,
+        }))
+      )
+      .rpc();
+
+    const proposalAccounts = [
+      { pubkey: recipient.publicKey, isSigner: false, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+    ];
+    await program.methods.executeProposal(PROPOSAL_POLL_ID).remainingAccounts(proposalAccounts).rpc();
+
+    expect(await program.provider.connection.getBalance(recipient.publicKey)).toEqual(LAMPORTS_PER_SOL / 100);
+    await expect(
+      program.methods.executeProposal(PROPOSAL_POLL_ID).remainingAccounts(proposalAccounts).rpc()
+    ).rejects.toThrow(/ProposalAlreadyExecuted/);
+
+    // Closing the poll leaves what the proposal did not spend in the treasury.
+    const leftover = await program.provider.connection.getBalance(treasury);
+    await program.methods
+      .closePoll(PROPOSAL_POLL_ID)
+      .accountsPartial({ proposal: PublicKey.findProgramAddressSync([Buffer.from("proposal"), pollIdBytes], PROGRAM_ID)[0] })
+      .remainingAccounts(
+        [0, 1].map((index) => ({
+          pubkey: candidateAddress(PROPOSAL_POLL_ID, index),
+          isSigner: false,
+          isWritable: true,
+        }))
+      )
+      .rpc();
+    expect(await program.provider.connection.getBalance(treasury)).toEqual(leftover);
+  }, 60_000);
+
+  it("escrows gate mint tokens for the poll and returns them once it ends", async () => {
+    const NFT_POLL_ID = new BN(14);
+    const pollIdBytes = NFT_POLL_ID.toArrayLike(Buffer, "le", 8);
+    const [nftPollAddress] = PublicKey.findProgramAddressSync([Buffer.from("poll"), pollIdBytes], PROGRAM_ID);
+    const ASSOCIATED_TOKEN_PROGRAM_ID = new PublicKey("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+    const payer = program.provider.publicKey;
+    const collector = await fundedKeypair();
+    // Like a Labubu type: one mint shared by every holder.
+    const mint = await createMint();
+    const holderAccount = await createTokenAccount(mint, payer, 2);
+    const collectorAccount = await createTokenAccount(mint, collector.publicKey, 1);
+    const [pollVault] = PublicKey.findProgramAddressSync(
+      [nftPollAddress.toBuffer(), TOKEN_PROGRAM_ID.toBuffer(), mint.toBuffer()],
+      ASSOCIATED_TOKEN_PROGRAM_ID
+    );
+    const nftVoteAddress = (tokenAccount: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("nft_vote"), pollIdBytes, mint.toBuffer(), tokenAccount.toBuffer()],
+        PROGRAM_ID
+      )[0];
+    const nftAccounts = (tokenAccount: PublicKey) => [
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: tokenAccount, isSigner: false, isWritable: true },
+      { pubkey: pollVault, isSigner: false, isWritable: true },
+      { pubkey: nftVoteAddress(tokenAccount), isSigner: false, isWritable: true },
+    ];
+    const now = Math.floor(Date.now() / 1000);
+    const end = now + 20;
+
+    await program.methods
+      .initializePoll(NFT_POLL_ID, new BN(now - 10), new BN(end), "Collectors", "Holders only", pollSettings({
+        gateMints: [mint],
+      }))
+      .rpc();
+    for (const name of ["Alice", "Bob"]) {
+      await program.methods.initializeCandidate(NFT_POLL_ID, name).rpc();
+    }
+
+    await expect(
+      program.methods.vote(NFT_POLL_ID, new BN(0), new BN(1), null).rpc()
+    ).rejects.toThrow(/WrongBallotMode/);
+
+    // Two holders of the same mint each vote with their own tokens.
+    await program.methods
+      .voteWithNft(NFT_POLL_ID, new BN(0))
+      .accountsPartial({ tokenProgram: TOKEN_PROGRAM_ID })
+      .remainingAccounts(nftAccounts(holderAccount))
+      .rpc();
+    await program.methods
+      .voteWithNft(NFT_POLL_ID, new BN(1))
+      .accountsPartial({ signer: collector.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+      .remainingAccounts(nftAccounts(collectorAccount))
+      .signers([collector])
+      .rpc();
+
+    const alice = await program.account.candidateAccount.fetch(candidateAddress(NFT_POLL_ID, 0));
+    expect(alice.candidateVotes.toNumber()).toEqual(2);
+    const bob = await program.account.candidateAccount.fetch(candidateAddress(NFT_POLL_ID, 1));
+    expect(bob.candidateVotes.toNumber()).toEqual(1);
+    const nftVote = await program.account.nftVote.fetch(nftVoteAddress(holderAccount));
+    expect(nftVote.voter.toBase58()).toEqual(payer.toBase58());
+    expect(nftVote.votes.toNumber()).toEqual(2);
+
+    // The tokens sit in the poll's account, so there is nothing left to
+    // transfer to another wallet and vote with again.
+    expect(await tokenBalance(holderAccount)).toEqual(0);
+    expect(await tokenBalance(pollVault)).toEqual(3);
+    await expect(
+      program.methods
+        .voteWithNft(NFT_POLL_ID, new BN(1))
+        .accountsPartial({ tokenProgram: TOKEN_PROGRAM_ID })
+        .remainingAccounts(nftAccounts(holderAccount))
+        .rpc()
+    ).rejects.toThrow(/NftAlreadyUsed/);
+
+    const unlock = (tokenAccount: PublicKey, signer = payer) =>
+      program.methods.unlockNft(NFT_POLL_ID).accountsPartial({
+        signer,
+        mint,
+        voterTokenAccount: tokenAccount,
+        pollVault,
+        nftVote: nftVoteAddress(tokenAccount),
+        tokenProgram: TOKEN_PROGRAM_ID,
+      });
+
+    await expect(unlock(holderAccount).rpc()).rejects.toThrow(/TokensLocked/);
+
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+
+    await unlock(holderAccount).rpc();
+    expect(await tokenBalance(holderAccount)).toEqual(2);
+    expect(await program.provider.connection.getAccountInfo(nftVoteAddress(holderAccount))).toBeNull();
+
+    await unlock(collectorAccount, collector.publicKey).signers([collector]).rpc();
+    expect(await tokenBalance(collectorAccount)).toEqual(1);
+    expect(await program.provider.connection.getAccountInfo(pollVault)).toBeNull();
+    const pollAccount = await program.account.pollAccount.fetch(nftPollAddress);
+    expect(pollAccount.openVaults.toNumber()).toEqual(0);
+  }, 60_000);
+
+  it("counts a relayed ballot for its signer and blocks replays", async () => {
+    const RELAY_POLL_ID = new BN(15);
+    const voter = Keypair.generate();
+    const [receiptAddress] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), RELAY_POLL_ID.toArrayLike(Buffer, "le", 8), voter.publicKey.toBuffer()],
+      PROGRAM_ID
+    );
+
+    await program.methods
+      .initializePoll(RELAY_POLL_ID, new BN(0), new BN(1893456000), "Gasless", "Relayed ballots", pollSettings())
+      .rpc();
+    for (const name of ["Alice", "Bob"]) {
+      await program.methods.initializeCandidate(RELAY_POLL_ID, name).rpc();
+    }
+
+    const signedBallot = (candidateIndex: number, nonce: number) =>
+      Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: voter.secretKey,
+        message: Buffer.concat([
+          PROGRAM_ID.toBuffer(),
+          RELAY_POLL_ID.toArrayLike(Buffer, "le", 8),
+          new BN(candidateIndex).toArrayLike(Buffer, "le", 8),
+          new BN(nonce).toArrayLike(Buffer, "le", 8),
+        ]),
+      });
+
+    // The ballot was signed for Bob, so it cannot be relayed as a vote for Alice.
+    await expect(
+      program.methods
+        .relayVote(RELAY_POLL_ID, new BN(0), voter.publicKey, new BN(0), null)
+        .preInstructions([signedBallot(1, 0)])
+        .rpc()
+    ).rejects.toThrow(/InvalidRelaySignature/);
+
+    await program.methods
+      .relayVote(RELAY_POLL_ID, new BN(1), voter.publicKey, new BN(0), null)
+      .preInstructions([signedBallot(1, 0)])
+      .rpc();
+
+    const receipt = await program.account.voterReceipt.fetch(receiptAddress);
+    expect(receipt.voter.toBase58()).toEqual(voter.publicKey.toBase58());
+    const bob = await program.account.candidateAccount.fetch(candidateAddress(RELAY_POLL_ID, 1));
+    expect(bob.candidateVotes.toNumber()).toEqual(1);
+
+    await expect(
+      program.methods
+        .relayVote(RELAY_POLL_ID, new BN(1), voter.publicKey, new BN(0), null)
+        .preInstructions([signedBallot(1, 0)])
+        .rpc()
+    ).rejects.toThrow(/InvalidNonce/);
   });
+
+  it("locks governance tokens for the vote weight until the poll ends", async () => {
+    const TOKEN_POLL_ID = new BN(16);
+    const pollIdBytes = TOKEN_POLL_ID.toArrayLike(Buffer, "le", 8);
+    const [tokenPollAddress] = PublicKey.findProgramAddressSync([Buffer.from("poll"), pollIdBytes], PROGRAM_ID);
+    const payer = program.provider.publicKey;
+    const voter = await fundedKeypair();
+    const governanceMint = await createMint();
+    const payerTokens = await createTokenAccount(governanceMint, payer, 5);
+    const voterTokens = await createTokenAccount(governanceMint, voter.publicKey, 3);
+    const vaultAddress = (owner: PublicKey) =>
+      PublicKey.findProgramAddressSync([Buffer.from("vault"), pollIdBytes, owner.toBuffer()], PROGRAM_ID)[0];
+    const tokenAccounts = (owner: PublicKey, voterTokenAccount: PublicKey) => ({
+      signer: owner,
+      governanceMint,
+      voterTokenAccount,
+      voteVault: vaultAddress(owner),
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+    const now = Math.floor(Date.now() / 1000);
+    const end = now + 20;
+
+    await program.methods
+      .initializePoll(TOKEN_POLL_ID, new BN(now - 10), new BN(end), "Treasury", "Token-weighted poll", pollSettings())
+      .accountsPartial({ governanceMint })
+      .rpc();
+    const candidates = [];
+    for (const name of ["Alice", "Bob"]) {
+      await program.methods.initializeCandidate(TOKEN_POLL_ID, name).rpc();
+      candidates.push({
+        pubkey: candidateAddress(TOKEN_POLL_ID, candidates.length),
+        isSigner: false,
+        isWritable: true,
+      });
+    }
+
+    // The whole balance is moved into the voter's vault and counted as weight.
+    await program.methods
+      .vote(TOKEN_POLL_ID, new BN(0), new BN(1), null)
+      .accountsPartial(tokenAccounts(payer, payerTokens))
+      .rpc();
+    await program.methods
+      .vote(TOKEN_POLL_ID, new BN(1), new BN(1), null)
+      .accountsPartial(tokenAccounts(voter.publicKey, voterTokens))
+      .signers([voter])
+      .rpc();
+
+    expect(await tokenBalance(payerTokens)).toEqual(0);
+    expect(await tokenBalance(vaultAddress(payer))).toEqual(5);
+    const alice = await program.account.candidateAccount.fetch(candidates[0].pubkey);
+    expect(alice.candidateVotes.toNumber()).toEqual(5);
+    const bob = await program.account.candidateAccount.fetch(candidates[1].pubkey);
+    expect(bob.candidateVotes.toNumber()).toEqual(3);
+    let pollAccount = await program.account.pollAccount.fetch(tokenPollAddress);
+    expect(pollAccount.openVaults.toNumber()).toEqual(2);
+
+    // The locked tokens cannot be voted a second time, whether or not new ones arrive.
+    await expect(
+      program.methods
+        .vote(TOKEN_POLL_ID, new BN(1), new BN(1), null)
+        .accountsPartial(tokenAccounts(payer, payerTokens))
+        .rpc()
+    ).rejects.toThrow(/AlreadyVoted/);
+    const emptyWallet = await fundedKeypair();
+    await expect(
+      program.methods
+        .vote(TOKEN_POLL_ID, new BN(1), new BN(1), null)
+        .accountsPartial(
+          tokenAccounts(emptyWallet.publicKey, await createTokenAccount(governanceMint, emptyWallet.publicKey))
+        )
+        .signers([emptyWallet])
+        .rpc()
+    ).rejects.toThrow(/NoVotingPower/);
+
+    // Approval ballots don't lock tokens, so they are refused on token-weighted polls.
+    await expect(
+      program.methods
+        .voteMany(TOKEN_POLL_ID, [new BN(1)], null)
+        .accounts({ signer: emptyWallet.publicKey })
+        .remainingAccounts([candidates[1]])
+        .signers([emptyWallet])
+        .rpc()
+    ).rejects.toThrow(/WrongBallotMode/);
+
+    const unlock = (owner: PublicKey, voterTokenAccount: PublicKey) =>
+      program.methods.unlockTokens(TOKEN_POLL_ID).accountsPartial(tokenAccounts(owner, voterTokenAccount));
+
+    await expect(unlock(payer, payerTokens).rpc()).rejects.toThrow(/TokensLocked/);
+
+    await sleep((end - Math.floor(Date.now() / 1000) + 2) * 1000);
+    await program.methods
+      .finalizePoll(TOKEN_POLL_ID)
+      .remainingAccounts(candidates.map((candidate) => ({ ...candidate, isWritable: false })))
+      .rpc();
+    pollAccount = await program.account.pollAccount.fetch(tokenPollAddress);
+    expect(pollAccount.winner.toNumber()).toEqual(0);
+
+    // Closing the poll would strand the tokens still in their vaults.
+    await unlock(payer, payerTokens).rpc();
+    expect(await tokenBalance(payerTokens)).toEqual(5);
+    await expect(
+      program.methods.closePoll(TOKEN_POLL_ID).remainingAccounts(candidates).rpc()
+    ).rejects.toThrow(/TokensStillLocked/);
+
+    await unlock(voter.publicKey, voterTokens).signers([voter]).rpc();
+    expect(await tokenBalance(voterTokens)).toEqual(3);
+    pollAccount = await program.account.pollAccount.fetch(tokenPollAddress);
+    expect(pollAccount.openVaults.toNumber()).toEqual(0);
+
+    await program.methods.closePoll(TOKEN_POLL_ID).remainingAccounts(candidates).rpc();
+    expect(await program.provider.connection.getAccountInfo(tokenPollAddress)).toBeNull();
+  }, 60_000);
 });
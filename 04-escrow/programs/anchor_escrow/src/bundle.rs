@@ -0,0 +1,239 @@
+use crate::{
+    errors::EscrowError,
+    extensions::{check_mint_supported, gross_up},
+    state::{Asset, Escrow, Leg, MAX_EXTRA_LEGS},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{create_idempotent, get_associated_token_address_with_program_id, Create},
+    token_interface::{
+        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TransferChecked,
+    },
+};
+
+/// Checks the extra legs of a bundle: at most `MAX_EXTRA_LEGS`, non-zero
+/// amounts, and distinct mints that differ from the primary leg's asset.
+pub fn validate_legs(legs: &[Leg], primary: &Asset) -> Result<()> {
+    require_gte!(MAX_EXTRA_LEGS, legs.len(), EscrowError::InvalidBundle);
+    for (i, leg) in legs.iter().enumerate() {
+        require_gt!(leg.amount, 0, EscrowError::InvalidAmount);
+        require!(
+            Asset::Token(leg.mint) != *primary
+                && legs[..i].iter().all(|other| other.mint != leg.mint),
+            EscrowError::InvalidBundle
+        );
+    }
+    Ok(())
+}
+
+/// Splits `accounts` into one chunk of `per_leg` accounts for each leg.
+fn leg_accounts<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    legs: usize,
+    per_leg: usize,
+) -> Result<std::slice::Chunks<'info, AccountInfo<'info>>> {
+    require_eq!(
+        accounts.len(),
+        legs * per_leg,
+        EscrowError::InvalidBundleAccounts
+    );
+    Ok(accounts.chunks(per_leg))
+}
+
+/// Moves the extra legs of a bundle escrow. Their accounts are passed as
+/// remaining accounts, in the order of the legs stored on the `Escrow`, and
+/// checked against them here. Every leg uses the escrow's token program.
+pub struct Bundle<'info> {
+    /// Pays for any associated token account that has to be created.
+    pub payer: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub associated_token_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
+
+impl<'info> Bundle<'info> {
+    /// Make: `[mint, maker_ata, vault]` per leg. Creates each vault as the
+    /// escrow's associated token account and deposits the leg's amount. Like
+    /// the primary deposit, each leg then records what reached its vault after
+    /// any Token-2022 transfer fee.
+    pub fn deposit(
+        &self,
+        legs: &mut [Leg],
+        accounts: &'info [AccountInfo<'info>],
+        maker: &AccountInfo<'info>,
+        escrow: &AccountInfo<'info>,
+    ) -> Result<()> {
+        let chunks = leg_accounts(accounts, legs.len(), 3)?;
+        for (leg, chunk) in legs.iter_mut().zip(chunks) {
+            let [mint_info, maker_ata, vault] = chunk else {
+                return err!(EscrowError::InvalidBundleAccounts);
+            };
+            let mint = self.load_mint(mint_info, leg)?;
+            self.create_ata(vault, escrow, mint_info)?;
+            self.transfer(maker_ata, vault, &mint, maker, leg.amount, &[])?;
+
+            leg.amount = InterfaceAccount::<TokenAccount>::try_from(vault)?.amount;
+            require_gt!(leg.amount, 0, EscrowError::InvalidAmount);
+        }
+        Ok(())
+    }
+
+    /// Take, refund and expire: `[mint, vault, recipient_ata]` per leg.
+    /// Empties each vault into the recipient's associated token account,
+    /// created if needed, and closes the vault with its rent going to
+    /// `rent_destination`.
+    pub fn release(
+        &self,
+        legs: &[Leg],
+        accounts: &'info [AccountInfo<'info>],
+        escrow: &AccountInfo<'info>,
+        recipient: &AccountInfo<'info>,
+        rent_destination: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        for (leg, chunk) in legs.iter().zip(leg_accounts(accounts, legs.len(), 3)?) {
+            let [mint_info, vault_info, recipient_ata] = chunk else {
+                return err!(EscrowError::InvalidBundleAccounts);
+            };
+            let mint = self.load_mint(mint_info, leg)?;
+            self.check_ata(vault_info, escrow.key, mint_info.key)?;
+            self.create_ata(recipient_ata, recipient, mint_info)?;
+
+            let vault = InterfaceAccount::<TokenAccount>::try_from(vault_info)?;
+            self.transfer(
+                vault_info,
+                recipient_ata,
+                &mint,
+                escrow,
+                vault.amount,
+                signer_seeds,
+            )?;
+            close_account(CpiContext::new_with_signer(
+                self.token_program.key(),
+                CloseAccount {
+                    account: vault_info.clone(),
+                    destination: rent_destination.clone(),
+                    authority: escrow.clone(),
+                },
+                signer_seeds,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Take: `[mint, taker_ata, maker_ata, treasury_ata]` per leg. Pays each
+    /// leg's amount from the taker, split between the treasury and the maker
+    /// and grossed up for transfer fees like the primary leg. The treasury is
+    /// only needed when the escrow charges a fee.
+    pub fn pay(
+        &self,
+        escrow: &Escrow,
+        accounts: &'info [AccountInfo<'info>],
+        taker: &AccountInfo<'info>,
+        maker: &AccountInfo<'info>,
+        treasury: Option<&AccountInfo<'info>>,
+    ) -> Result<()> {
+        let legs = &escrow.extra_requested;
+        for (leg, chunk) in legs.iter().zip(leg_accounts(accounts, legs.len(), 4)?) {
+            let [mint_info, taker_ata, maker_ata, treasury_ata] = chunk else {
+                return err!(EscrowError::InvalidBundleAccounts);
+            };
+            let mint = self.load_mint(mint_info, leg)?;
+
+            let fee = escrow.fee_for(leg.amount);
+            if fee > 0 {
+                let treasury = treasury.ok_or(EscrowError::InvalidTreasury)?;
+                self.create_ata(treasury_ata, treasury, mint_info)?;
+                self.transfer(
+                    taker_ata,
+                    treasury_ata,
+                    &mint,
+                    taker,
+                    gross_up(mint_info, fee)?,
+                    &[],
+                )?;
+            }
+            self.create_ata(maker_ata, maker, mint_info)?;
+            self.transfer(
+                taker_ata,
+                maker_ata,
+                &mint,
+                taker,
+                gross_up(mint_info, leg.amount - fee)?,
+                &[],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn load_mint(
+        &self,
+        mint_info: &'info AccountInfo<'info>,
+        leg: &Leg,
+    ) -> Result<InterfaceAccount<'info, Mint>> {
+        require_keys_eq!(
+            mint_info.key(),
+            leg.mint,
+            EscrowError::InvalidBundleAccounts
+        );
+        require_keys_eq!(
+            *mint_info.owner,
+            self.token_program.key(),
+            EscrowError::InvalidBundleAccounts
+        );
+        check_mint_supported(mint_info)?;
+        InterfaceAccount::<Mint>::try_from(mint_info)
+    }
+
+    fn check_ata(&self, ata: &AccountInfo<'info>, owner: &Pubkey, mint: &Pubkey) -> Result<()> {
+        let expected =
+            get_associated_token_address_with_program_id(owner, mint, self.token_program.key);
+        require_keys_eq!(ata.key(), expected, EscrowError::InvalidBundleAccounts);
+        Ok(())
+    }
+
+    fn create_ata(
+        &self,
+        ata: &AccountInfo<'info>,
+        owner: &AccountInfo<'info>,
+        mint: &AccountInfo<'info>,
+    ) -> Result<()> {
+        self.check_ata(ata, owner.key, mint.key)?;
+        create_idempotent(CpiContext::new(
+            self.associated_token_program.key(),
+            Create {
+                payer: self.payer.clone(),
+                associated_token: ata.clone(),
+                authority: owner.clone(),
+                mint: mint.clone(),
+                system_program: self.system_program.clone(),
+                token_program: self.token_program.clone(),
+            },
+        ))
+    }
+
+    fn transfer(
+        &self,
+        from: &AccountInfo<'info>,
+        to: &AccountInfo<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        authority: &AccountInfo<'info>,
+        amount: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.key(),
+                TransferChecked {
+                    from: from.clone(),
+                    mint: mint.to_account_info(),
+                    to: to.clone(),
+                    authority: authority.clone(),
+                },
+                signer_seeds,
+            ),
+            amount,
+            mint.decimals,
+        )
+    }
+}
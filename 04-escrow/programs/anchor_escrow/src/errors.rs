@@ -10,4 +10,32 @@ pub enum EscrowError {
   InvalidMintA,
   #[msg("Invalid mint b")]
   InvalidMintB,
+  #[msg("Expiry must be in the future")]
+  InvalidExpiry,
+  #[msg("Escrow has expired")]
+  EscrowExpired,
+  #[msg("Escrow has not expired")]
+  EscrowNotExpired,
+  #[msg("Invalid taker")]
+  InvalidTaker,
+  #[msg("Fee cannot exceed 1000 bps")]
+  InvalidFee,
+  #[msg("Invalid fee authority")]
+  InvalidFeeAuthority,
+  #[msg("Invalid treasury")]
+  InvalidTreasury,
+  #[msg("Invalid bundle legs")]
+  InvalidBundle,
+  #[msg("Invalid bundle accounts")]
+  InvalidBundleAccounts,
+  #[msg("Bundle escrows can only be taken whole")]
+  BundleNotDivisible,
+  #[msg("Token accounts are required for token legs")]
+  MissingTokenAccounts,
+  #[msg("Mints with a transfer hook or non-transferable mints are not supported")]
+  UnsupportedMint,
+  #[msg("Only the program's upgrade authority can create the config")]
+  NotUpgradeAuthority,
+  #[msg("Escrow terms changed since they were quoted")]
+  TermsChanged,
 }
\ No newline at end of file
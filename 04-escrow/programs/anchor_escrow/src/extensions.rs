@@ -0,0 +1,52 @@
+use crate::errors::EscrowError;
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        non_transferable::NonTransferable, transfer_fee::TransferFeeConfig,
+        transfer_hook::TransferHook, BaseStateWithExtensions, StateWithExtensions,
+    },
+    state::Mint,
+};
+
+/// Rejects Token-2022 mints the escrow can't settle: non-transferable mints,
+/// and mints with the transfer hook extension, whose extra accounts the
+/// escrow's transfers don't forward. Every other extension is allowed.
+pub fn check_mint_supported(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+
+    require!(
+        mint.get_extension::<NonTransferable>().is_err(),
+        EscrowError::UnsupportedMint
+    );
+    // Even an unset hook program can be set later by the mint's hook
+    // authority, which would strand whatever the escrow holds.
+    require!(
+        mint.get_extension::<TransferHook>().is_err(),
+        EscrowError::UnsupportedMint
+    );
+    Ok(())
+}
+
+/// Amount to send so that `net` arrives after the mint's Token-2022 transfer
+/// fee for the current epoch. Mints without the extension have no fee.
+pub fn gross_up(mint: &AccountInfo, net: u64) -> Result<u64> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(net);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    let Ok(fee_config) = mint.get_extension::<TransferFeeConfig>() else {
+        return Ok(net);
+    };
+
+    let fee = fee_config
+        .calculate_inverse_epoch_fee(Clock::get()?.epoch, net)
+        .ok_or(EscrowError::InvalidAmount)?;
+    net.checked_add(fee)
+        .ok_or_else(|| EscrowError::InvalidAmount.into())
+}
@@ -0,0 +1,147 @@
+use crate::{
+    errors::EscrowError,
+    state::{Asset, Escrow},
+    transfers::EscrowDeposit,
+};
+use anchor_lang::{
+    prelude::*,
+    system_program::{transfer, Transfer},
+};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+/// Reprices an open escrow in place, keeping its seed and accounts.
+#[derive(Accounts)]
+pub struct Amend<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+        has_one = maker @ EscrowError::InvalidMaker,
+        constraint = escrow.mint_a.matches(mint_a.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintA,
+    )]
+    pub escrow: Box<Account<'info, Escrow>>,
+
+    // Token accounts, omitted when the escrow offers native SOL
+    #[account(mint::token_program = token_program)]
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        init_if_needed,
+        payer = maker,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program
+    )]
+    pub maker_ata_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Amend<'info> {
+    fn top_up(&mut self, amount: u64) -> Result<()> {
+        if self.escrow.mint_a == Asset::Sol {
+            transfer(
+                CpiContext::new(
+                    self.system_program.key(),
+                    Transfer {
+                        from: self.maker.to_account_info(),
+                        to: self.escrow.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+            self.escrow.deposit += amount;
+            return Ok(());
+        }
+        let (Some(mint_a), Some(maker_ata_a), Some(vault)) =
+            (&self.mint_a, &self.maker_ata_a, &mut self.vault)
+        else {
+            return err!(EscrowError::MissingTokenAccounts);
+        };
+
+        let before = vault.amount;
+        transfer_checked(
+            CpiContext::new(
+                self.token_program.key(),
+                TransferChecked {
+                    from: maker_ata_a.to_account_info(),
+                    to: vault.to_account_info(),
+                    mint: mint_a.to_account_info(),
+                    authority: self.maker.to_account_info(),
+                },
+            ),
+            amount,
+            mint_a.decimals,
+        )?;
+
+        // Like on `make`, only what reaches the vault after a Token-2022
+        // transfer fee counts towards the deposit.
+        vault.reload()?;
+        self.escrow.deposit += vault.amount - before;
+        Ok(())
+    }
+
+    fn withdraw(&mut self, amount: u64) -> Result<()> {
+        EscrowDeposit {
+            escrow: &self.escrow,
+            mint_a: self.mint_a.as_deref(),
+            vault: self.vault.as_deref(),
+            token_program: &self.token_program,
+        }
+        .withdraw(
+            &self.maker.to_account_info(),
+            self.maker_ata_a.as_deref(),
+            amount,
+        )?;
+        self.escrow.deposit -= amount;
+        Ok(())
+    }
+}
+
+pub fn handler(ctx: Context<Amend>, receive: u64, deposit: u64, expiry: Option<i64>) -> Result<()> {
+    require_gt!(receive, 0, EscrowError::InvalidAmount);
+    require_gt!(deposit, 0, EscrowError::InvalidAmount);
+
+    let now = Clock::get()?.unix_timestamp;
+    match expiry {
+        Some(expiry) => require_gt!(expiry, now, EscrowError::InvalidExpiry),
+        None => require!(
+            !ctx.accounts.escrow.is_expired(now),
+            EscrowError::EscrowExpired
+        ),
+    }
+
+    let current = ctx.accounts.escrow.deposit;
+    if deposit > current {
+        ctx.accounts.top_up(deposit - current)?;
+    } else if deposit < current {
+        ctx.accounts.withdraw(current - deposit)?;
+    }
+
+    let escrow = &mut ctx.accounts.escrow;
+    escrow.receive = receive;
+    // `None` keeps the current expiry, so one can be added or moved but never
+    // cleared.
+    if expiry.is_some() {
+        escrow.expiry = expiry;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::{
+    bundle::Bundle,
+    errors::EscrowError,
+    state::Escrow,
+    transfers::{EscrowDeposit, EscrowSigner},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+/// Permissionless cleanup of an expired escrow. The cranker gets the escrow
+/// account's rent as a bounty; the vault's rent goes back to the maker.
+#[derive(Accounts)]
+pub struct Expire<'info> {
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        close = cranker,
+        seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+        has_one = maker @ EscrowError::InvalidMaker,
+        constraint = escrow.mint_a.matches(mint_a.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintA,
+    )]
+    pub escrow: Box<Account<'info, Escrow>>,
+
+    // Token accounts, omitted when the escrow offers native SOL
+    #[account(mint::token_program = token_program)]
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program
+    )]
+    pub maker_ata_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Expire<'info> {
+    fn return_and_close_vault(&self) -> Result<()> {
+        // The escrow's rent is the cranker's bounty, so the deposit has
+        // to go back to the maker before it closes.
+        let deposit = EscrowDeposit {
+            escrow: &self.escrow,
+            mint_a: self.mint_a.as_deref(),
+            vault: self.vault.as_deref(),
+            token_program: &self.token_program,
+        };
+        deposit.withdraw(
+            &self.maker.to_account_info(),
+            self.maker_ata_a.as_deref(),
+            deposit.balance(),
+        )?;
+        deposit.close_vault(&self.maker.to_account_info())
+    }
+
+    /// Returns the bundle's extra offered mints to the maker, see
+    /// `Bundle::release` for the remaining accounts.
+    fn return_extra_legs(&self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let signer = EscrowSigner::new(&self.escrow);
+        Bundle {
+            payer: self.cranker.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        }
+        .release(
+            &self.escrow.extra_offered,
+            remaining_accounts,
+            &self.escrow.to_account_info(),
+            &self.maker.to_account_info(),
+            &self.maker.to_account_info(),
+            &[&signer.seeds()[..]],
+        )
+    }
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, 'info, 'info, Expire<'info>>) -> Result<()> {
+    require!(
+        ctx.accounts.escrow.is_expired(Clock::get()?.unix_timestamp),
+        EscrowError::EscrowNotExpired
+    );
+
+    ctx.accounts.return_and_close_vault()?;
+    ctx.accounts.return_extra_legs(ctx.remaining_accounts)?;
+
+    Ok(())
+}
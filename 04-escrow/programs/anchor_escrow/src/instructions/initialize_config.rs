@@ -0,0 +1,42 @@
+use crate::{errors::EscrowError, program::AnchorEscrow, state::Config};
+use anchor_lang::prelude::*;
+
+/// Creates the venue's fee config. Only the program's upgrade authority can
+/// call it, and it becomes the first fee authority.
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub fee_authority: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, AnchorEscrow>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(fee_authority.key())
+            @ EscrowError::NotUpgradeAuthority,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(
+        init,
+        payer = fee_authority,
+        space = Config::INIT_SPACE + Config::DISCRIMINATOR.len(),
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<InitializeConfig>, fee_bps: u16, treasury: Pubkey) -> Result<()> {
+    require_gte!(Config::MAX_FEE_BPS, fee_bps, EscrowError::InvalidFee);
+
+    ctx.accounts.config.set_inner(Config {
+        fee_authority: ctx.accounts.fee_authority.key(),
+        fee_bps,
+        treasury,
+        bump: ctx.bumps.config,
+    });
+    Ok(())
+}
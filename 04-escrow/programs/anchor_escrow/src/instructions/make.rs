@@ -1,6 +1,11 @@
+use crate::bundle::{validate_legs, Bundle};
 use crate::errors::EscrowError;
-use crate::state::Escrow;
-use anchor_lang::prelude::*;
+use crate::extensions::check_mint_supported;
+use crate::state::{Asset, Config, Escrow, Leg};
+use anchor_lang::{
+    prelude::*,
+    system_program::{transfer, Transfer},
+};
 
 use anchor_spl::{
     associated_token::AssociatedToken,
@@ -19,15 +24,15 @@ pub struct Make<'info> {
         bump,
     )]
     pub escrow: Account<'info, Escrow>,
-    //Token accounts
+    //Token accounts, omitted on a side that trades native SOL
     #[account(
       mint::token_program=token_program,
     )]
-    pub mint_a: InterfaceAccount<'info, Mint>,
+    pub mint_a: Option<InterfaceAccount<'info, Mint>>,
     #[account(
         mint::token_program=token_program,
     )]
-    pub mint_b: InterfaceAccount<'info, Mint>,
+    pub mint_b: Option<InterfaceAccount<'info, Mint>>,
     #[account(
         mut,
         associated_token::mint=mint_a,
@@ -35,7 +40,7 @@ pub struct Make<'info> {
         associated_token::token_program=token_program,
 
     )]
-    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init,
         payer=maker,
@@ -43,7 +48,14 @@ pub struct Make<'info> {
         associated_token::authority=escrow,
         associated_token::token_program=token_program,
     )]
-    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: the config PDA, always passed so the maker can't skip the fee;
+    /// read by `fee_bps` once it is initialized
+    #[account(
+        seeds=[b"config"],
+        bump,
+    )]
+    pub config: UncheckedAccount<'info>,
 
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
@@ -51,41 +63,155 @@ pub struct Make<'info> {
 }
 
 impl<'info> Make<'info> {
-    fn populate_escrow(&mut self, seed: u64, amount: u64, bump: u8) -> Result<()> {
+    /// The fee the taker will pay, zero while the venue has no config.
+    fn fee_bps(&self) -> Result<u16> {
+        if self.config.data_is_empty() {
+            return Ok(0);
+        }
+        require_keys_eq!(
+            *self.config.owner,
+            crate::ID,
+            ErrorCode::AccountOwnedByWrongProgram
+        );
+        let config = Config::try_deserialize(&mut &self.config.try_borrow_data()?[..])?;
+        Ok(config.fee_bps)
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn populate_escrow(
+        &mut self,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        expiry: Option<i64>,
+        taker: Option<Pubkey>,
+        extra_offered: Vec<Leg>,
+        extra_requested: Vec<Leg>,
+        bump: u8,
+    ) -> Result<()> {
+        let fee_bps = self.fee_bps()?;
         self.escrow.set_inner(Escrow {
             seed,
             maker: self.maker.key(),
-            mint_a: self.mint_a.key(),
-            mint_b: self.mint_b.key(),
-            receive: amount,
+            mint_a: Asset::from_mint(self.mint_a.as_ref().map(|mint| mint.key())),
+            mint_b: Asset::from_mint(self.mint_b.as_ref().map(|mint| mint.key())),
+            receive,
+            deposit,
+            expiry,
+            taker,
+            fee_bps,
+            extra_offered,
+            extra_requested,
             bump,
         });
         Ok(())
     }
     fn deposit_tokens(&mut self, amount: u64) -> Result<()> {
+        let (Some(mint_a), Some(maker_ata_a), Some(vault)) =
+            (&self.mint_a, &self.maker_ata_a, &mut self.vault)
+        else {
+            return err!(EscrowError::MissingTokenAccounts);
+        };
         transfer_checked(
             CpiContext::new(
                 self.token_program.key(),
                 TransferChecked {
-                    from: self.maker_ata_a.to_account_info(),
-                    mint: self.mint_a.to_account_info(),
-                    to: self.vault.to_account_info(),
+                    from: maker_ata_a.to_account_info(),
+                    mint: mint_a.to_account_info(),
+                    to: vault.to_account_info(),
                     authority: self.maker.to_account_info(),
                 },
             ),
             amount,
-            self.mint_a.decimals,
+            mint_a.decimals,
         )?;
+
+        // A Token-2022 transfer fee is withheld from what reaches the vault,
+        // so the escrow records the net deposit.
+        vault.reload()?;
+        self.escrow.deposit = vault.amount;
+        require_gt!(self.escrow.deposit, 0, EscrowError::InvalidAmount);
         Ok(())
     }
+    /// A SOL offer is held by the escrow PDA itself, on top of its rent.
+    fn deposit_lamports(&mut self, amount: u64) -> Result<()> {
+        transfer(
+            CpiContext::new(
+                self.system_program.key(),
+                Transfer {
+                    from: self.maker.to_account_info(),
+                    to: self.escrow.to_account_info(),
+                },
+            ),
+            amount,
+        )
+    }
+    /// Locks the bundle's extra offered mints, see `Bundle::deposit` for the
+    /// remaining accounts.
+    fn deposit_extra_legs(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let escrow = self.escrow.to_account_info();
+        Bundle {
+            payer: self.maker.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        }
+        .deposit(
+            &mut self.escrow.extra_offered,
+            remaining_accounts,
+            &self.maker.to_account_info(),
+            &escrow,
+        )
+    }
 }
 
-pub fn handler(ctx: Context<Make>, seed: u64, receive: u64, amount: u64) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Make<'info>>,
+    seed: u64,
+    receive: u64,
+    amount: u64,
+    expiry: Option<i64>,
+    taker: Option<Pubkey>,
+    extra_offered: Vec<Leg>,
+    extra_requested: Vec<Leg>,
+) -> Result<()> {
     require_gt!(receive, 0, EscrowError::InvalidAmount);
     require_gt!(amount, 0, EscrowError::InvalidAmount);
+    if let Some(expiry) = expiry {
+        require_gt!(
+            expiry,
+            Clock::get()?.unix_timestamp,
+            EscrowError::InvalidExpiry
+        );
+    }
+    for mint in [&ctx.accounts.mint_a, &ctx.accounts.mint_b]
+        .into_iter()
+        .flatten()
+    {
+        check_mint_supported(&mint.to_account_info())?;
+    }
 
-    ctx.accounts
-        .populate_escrow(seed, receive, ctx.bumps.escrow)?;
-    ctx.accounts.deposit_tokens(amount)?;
+    ctx.accounts.populate_escrow(
+        seed,
+        receive,
+        amount,
+        expiry,
+        taker,
+        extra_offered,
+        extra_requested,
+        ctx.bumps.escrow,
+    )?;
+    let escrow = &ctx.accounts.escrow;
+    validate_legs(&escrow.extra_offered, &escrow.mint_a)?;
+    validate_legs(&escrow.extra_requested, &escrow.mint_b)?;
+
+    match ctx.accounts.escrow.mint_a {
+        Asset::Sol => ctx.accounts.deposit_lamports(amount)?,
+        Asset::Token(_) => ctx.accounts.deposit_tokens(amount)?,
+    }
+    ctx.accounts.deposit_extra_legs(ctx.remaining_accounts)?;
     Ok(())
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
-use crate::{errors::EscrowError, state::Escrow};
+use crate::{
+    bundle::Bundle,
+    errors::EscrowError,
+    state::Escrow,
+    transfers::{EscrowDeposit, EscrowSigner},
+};
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{
-        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
-        TransferChecked,
-    },
+    token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -19,12 +21,13 @@ pub struct Refund<'info> {
         seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
         bump = escrow.bump,
         has_one = maker @ EscrowError::InvalidMaker,
-        has_one = mint_a @ EscrowError::InvalidMintA,
+        constraint = escrow.mint_a.matches(mint_a.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintA,
     )]
     pub escrow: Box<Account<'info, Escrow>>,
 
+    // Token accounts, omitted when the escrow offers native SOL
     #[account(mint::token_program = token_program)]
-    pub mint_a: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
 
     #[account(
         mut,
@@ -32,7 +35,7 @@ pub struct Refund<'info> {
         associated_token::authority = escrow,
         associated_token::token_program = token_program
     )]
-    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     #[account(
         init_if_needed,
@@ -41,7 +44,7 @@ pub struct Refund<'info> {
         associated_token::authority = maker,
         associated_token::token_program = token_program
     )]
-    pub maker_ata_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub maker_ata_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     pub associated_token_program: Program<'info, AssociatedToken>,
 
@@ -51,45 +54,46 @@ pub struct Refund<'info> {
 }
 
 impl<'info> Refund<'info> {
-    fn withdraw_and_close_vault(&mut self) -> Result<()> {
-        let signer_seeds: [&[&[u8]]; 1] = [&[
-            b"escrow",
-            self.maker.to_account_info().key.as_ref(),
-            &self.escrow.seed.to_le_bytes()[..],
-            &[self.escrow.bump],
-        ]];
-
-        transfer_checked(
-            CpiContext::new_with_signer(
-                self.token_program.key(),
-                TransferChecked {
-                    from: self.vault.to_account_info(),
-                    to: self.maker_ata_a.to_account_info(),
-                    mint: self.mint_a.to_account_info(),
-                    authority: self.escrow.to_account_info(),
-                },
-                &signer_seeds,
-            ),
-            self.vault.amount,
-            self.mint_a.decimals,
+    fn withdraw_and_close_vault(&self) -> Result<()> {
+        // A SOL deposit sits in the escrow PDA itself, next to its rent.
+        let deposit = EscrowDeposit {
+            escrow: &self.escrow,
+            mint_a: self.mint_a.as_deref(),
+            vault: self.vault.as_deref(),
+            token_program: &self.token_program,
+        };
+        deposit.withdraw(
+            &self.maker.to_account_info(),
+            self.maker_ata_a.as_deref(),
+            deposit.balance(),
         )?;
+        deposit.close_vault(&self.maker.to_account_info())
+    }
 
-        close_account(CpiContext::new_with_signer(
-            self.token_program.key(),
-            CloseAccount {
-                account: self.vault.to_account_info(),
-                authority: self.escrow.to_account_info(),
-                destination: self.maker.to_account_info(),
-            },
-            &signer_seeds,
-        ))?;
-
-        Ok(())
+    /// Returns the bundle's extra offered mints to the maker, see
+    /// `Bundle::release` for the remaining accounts.
+    fn return_extra_legs(&self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let signer = EscrowSigner::new(&self.escrow);
+        Bundle {
+            payer: self.maker.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        }
+        .release(
+            &self.escrow.extra_offered,
+            remaining_accounts,
+            &self.escrow.to_account_info(),
+            &self.maker.to_account_info(),
+            &self.maker.to_account_info(),
+            &[&signer.seeds()[..]],
+        )
     }
 }
 
-pub fn handler(ctx: Context<Refund>) -> Result<()> {
+pub fn handler<'info>(ctx: Context<'_, '_, 'info, 'info, Refund<'info>>) -> Result<()> {
     ctx.accounts.withdraw_and_close_vault()?;
+    ctx.accounts.return_extra_legs(ctx.remaining_accounts)?;
 
     Ok(())
 }
\ No newline at end of file
@@ -1,13 +1,12 @@
+use crate::bundle::Bundle;
 use crate::errors::EscrowError;
-use crate::state::Escrow;
+use crate::state::{Config, Escrow};
+use crate::transfers::{EscrowDeposit, EscrowSigner, TakerPayment};
 use anchor_lang::prelude::*;
 
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{
-        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
-        TransferChecked,
-    },
+    token_interface::{Mint, TokenAccount, TokenInterface},
 };
 #[derive(Accounts)]
 pub struct Take<'info> {
@@ -21,20 +20,22 @@ pub struct Take<'info> {
         seeds=[b"escrow",maker.key().as_ref(),escrow.seed.to_le_bytes().as_ref()],
         bump=escrow.bump,
         has_one=maker @ EscrowError::InvalidMaker,
-        has_one=mint_a @ EscrowError::InvalidMintA,
-        has_one=mint_b @ EscrowError::InvalidMintB,
+        constraint=escrow.mint_a.matches(mint_a.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintA,
+        constraint=escrow.mint_b.matches(mint_b.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintB,
+        constraint=escrow.taker.is_none() || escrow.taker == Some(taker.key()) @ EscrowError::InvalidTaker,
     )]
     pub escrow: Box<Account<'info, Escrow>>,
 
-    pub mint_a: Box<InterfaceAccount<'info, Mint>>,
-    pub mint_b: Box<InterfaceAccount<'info, Mint>>,
+    // Token accounts, omitted on a side that trades native SOL
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+    pub mint_b: Option<Box<InterfaceAccount<'info, Mint>>>,
     #[account(
         mut,
         associated_token::mint=mint_a,
         associated_token::authority=escrow,
         associated_token::token_program=token_program,
     )]
-    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         init_if_needed,
         payer=taker,
@@ -42,14 +43,14 @@ pub struct Take<'info> {
         associated_token::authority=taker,
         associated_token::token_program=token_program,
     )]
-    pub taker_ata_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub taker_ata_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
        mut,
        associated_token::mint=mint_b,
        associated_token::authority=taker,
        associated_token::token_program=token_program,
     )]
-    pub taker_ata_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub taker_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         init_if_needed,
         payer=taker,
@@ -57,66 +58,128 @@ pub struct Take<'info> {
         associated_token::authority=maker,
         associated_token::token_program=token_program,
     )]
-    pub maker_ata_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub maker_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Fee accounts, omitted when the escrow was made without a fee
+    #[account(
+        seeds=[b"config"],
+        bump=config.bump,
+    )]
+    pub config: Option<Box<Account<'info, Config>>>,
+    /// CHECK: the authority of `treasury_ata_b`, or the fee recipient itself
+    /// when token B is SOL; must match `config.treasury`.
+    #[account(
+        mut,
+        constraint=config.as_ref().map(|config| config.treasury) == Some(treasury.key()) @ EscrowError::InvalidTreasury,
+    )]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(
+        init_if_needed,
+        payer=taker,
+        associated_token::mint=mint_b,
+        associated_token::authority=treasury,
+        associated_token::token_program=token_program,
+    )]
+    pub treasury_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Take<'info> {
-    fn transfer_to_maker(&mut self) -> Result<()> {
-        transfer_checked(
-            CpiContext::new(
-                self.token_program.key(),
-                TransferChecked {
-                    from: self.taker_ata_b.to_account_info(),
-                    to: self.maker_ata_b.to_account_info(),
-                    mint: self.mint_b.to_account_info(),
-                    authority: self.taker.to_account_info(),
-                },
-            ),
+    fn deposit(&self) -> EscrowDeposit<'_, 'info> {
+        EscrowDeposit {
+            escrow: &self.escrow,
+            mint_a: self.mint_a.as_deref(),
+            vault: self.vault.as_deref(),
+            token_program: &self.token_program,
+        }
+    }
+    /// Pays `escrow.receive` of token B, minus the protocol fee, to the maker
+    /// and the fee to the treasury.
+    fn transfer_to_maker(&self) -> Result<()> {
+        TakerPayment {
+            escrow: &self.escrow,
+            taker: &self.taker,
+            mint_b: self.mint_b.as_deref(),
+            taker_ata_b: self.taker_ata_b.as_deref(),
+            token_program: &self.token_program,
+            system_program: &self.system_program,
+        }
+        .pay_maker(
             self.escrow.receive,
-            self.mint_b.decimals,
-        )?;
-        Ok(())
+            &self.maker.to_account_info(),
+            self.maker_ata_b.as_deref(),
+            self.treasury.as_ref().map(|treasury| treasury.as_ref()),
+            self.treasury_ata_b.as_deref(),
+        )
     }
-    fn withdraw_and_close_vault(&mut self) -> Result<()> {
-        let signer_seeds: [&[&[u8]]; 1] = [&[
-            b"escrow",
-            self.maker.to_account_info().key.as_ref(),
-            &self.escrow.seed.to_le_bytes()[..],
-            &[self.escrow.bump],
-        ]];
-        transfer_checked(
-            CpiContext::new_with_signer(
-                self.token_program.key(),
-                TransferChecked {
-                    from: self.vault.to_account_info(),
-                    to: self.taker_ata_a.to_account_info(),
-                    mint: self.mint_a.to_account_info(),
-                    authority: self.escrow.to_account_info(),
-                },
-                &signer_seeds,
-            ),
-            self.vault.amount,
-            self.mint_a.decimals,
+    fn withdraw_and_close_vault(&self) -> Result<()> {
+        // The escrow is closed to the maker after this, so a SOL deposit has
+        // to leave it first.
+        let deposit = self.deposit();
+        deposit.withdraw(
+            &self.taker.to_account_info(),
+            self.taker_ata_a.as_deref(),
+            deposit.balance(),
         )?;
+        deposit.close_vault(&self.maker.to_account_info())
+    }
+    /// Moves the bundle's extra legs: the offered vaults to the taker, then
+    /// the requested mints to the maker and treasury. The remaining accounts
+    /// hold the `Bundle::release` accounts of every offered leg followed by
+    /// the `Bundle::pay` accounts of every requested leg.
+    fn settle_extra_legs(&self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let offered_accounts = self.escrow.extra_offered.len() * 3;
+        require_gte!(
+            remaining_accounts.len(),
+            offered_accounts,
+            EscrowError::InvalidBundleAccounts
+        );
+        let (offered, requested) = remaining_accounts.split_at(offered_accounts);
 
-        close_account(CpiContext::new_with_signer(
-            self.token_program.key(),
-            CloseAccount {
-                account: self.vault.to_account_info(),
-                authority: self.escrow.to_account_info(),
-                destination: self.maker.to_account_info(),
-            },
-            &signer_seeds,
-        ))?;
-        Ok(())
+        let signer = EscrowSigner::new(&self.escrow);
+        let bundle = Bundle {
+            payer: self.taker.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        };
+        bundle.release(
+            &self.escrow.extra_offered,
+            offered,
+            &self.escrow.to_account_info(),
+            &self.taker.to_account_info(),
+            &self.maker.to_account_info(),
+            &[&signer.seeds()[..]],
+        )?;
+        bundle.pay(
+            &self.escrow,
+            requested,
+            &self.taker.to_account_info(),
+            &self.maker.to_account_info(),
+            self.treasury.as_ref().map(|treasury| treasury.as_ref()),
+        )
     }
 }
 
-pub fn handler(ctx: Context<Take>) -> Result<()> {
+pub fn handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Take<'info>>,
+    expected_receive: u64,
+    expected_deposit: u64,
+) -> Result<()> {
+    require!(
+        !ctx.accounts.escrow.is_expired(Clock::get()?.unix_timestamp),
+        EscrowError::EscrowExpired
+    );
+    require!(
+        ctx.accounts
+            .escrow
+            .offers_at_least(expected_receive, expected_deposit),
+        EscrowError::TermsChanged
+    );
+
     ctx.accounts.transfer_to_maker()?;
     ctx.accounts.withdraw_and_close_vault()?;
+    ctx.accounts.settle_extra_legs(ctx.remaining_accounts)?;
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,188 @@
+use crate::errors::EscrowError;
+use crate::state::{Config, Escrow};
+use crate::transfers::{EscrowDeposit, TakerPayment};
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+#[derive(Accounts)]
+pub struct TakePartial<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds=[b"escrow",maker.key().as_ref(),escrow.seed.to_le_bytes().as_ref()],
+        bump=escrow.bump,
+        has_one=maker @ EscrowError::InvalidMaker,
+        constraint=escrow.mint_a.matches(mint_a.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintA,
+        constraint=escrow.mint_b.matches(mint_b.as_ref().map(|mint| mint.key())) @ EscrowError::InvalidMintB,
+        constraint=escrow.taker.is_none() || escrow.taker == Some(taker.key()) @ EscrowError::InvalidTaker,
+    )]
+    pub escrow: Box<Account<'info, Escrow>>,
+
+    // Token accounts, omitted on a side that trades native SOL
+    pub mint_a: Option<Box<InterfaceAccount<'info, Mint>>>,
+    pub mint_b: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(
+        mut,
+        associated_token::mint=mint_a,
+        associated_token::authority=escrow,
+        associated_token::token_program=token_program,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        init_if_needed,
+        payer=taker,
+        associated_token::mint=mint_a,
+        associated_token::authority=taker,
+        associated_token::token_program=token_program,
+    )]
+    pub taker_ata_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+       mut,
+       associated_token::mint=mint_b,
+       associated_token::authority=taker,
+       associated_token::token_program=token_program,
+    )]
+    pub taker_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        init_if_needed,
+        payer=taker,
+        associated_token::mint=mint_b,
+        associated_token::authority=maker,
+        associated_token::token_program=token_program,
+    )]
+    pub maker_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Fee accounts, omitted when the escrow was made without a fee
+    #[account(
+        seeds=[b"config"],
+        bump=config.bump,
+    )]
+    pub config: Option<Box<Account<'info, Config>>>,
+    /// CHECK: the authority of `treasury_ata_b`, or the fee recipient itself
+    /// when token B is SOL; must match `config.treasury`.
+    #[account(
+        mut,
+        constraint=config.as_ref().map(|config| config.treasury) == Some(treasury.key()) @ EscrowError::InvalidTreasury,
+    )]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    #[account(
+        init_if_needed,
+        payer=taker,
+        associated_token::mint=mint_b,
+        associated_token::authority=treasury,
+        associated_token::token_program=token_program,
+    )]
+    pub treasury_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> TakePartial<'info> {
+    /// Token B owed for `amount` of the remaining deposit, at the escrow's
+    /// price and rounded up in the maker's favour.
+    fn price_of(&self, amount: u64) -> Result<u64> {
+        let price =
+            (self.escrow.receive as u128 * amount as u128).div_ceil(self.escrow.deposit as u128);
+        u64::try_from(price).map_err(|_| EscrowError::InvalidAmount.into())
+    }
+    fn deposit(&self) -> EscrowDeposit<'_, 'info> {
+        EscrowDeposit {
+            escrow: &self.escrow,
+            mint_a: self.mint_a.as_deref(),
+            vault: self.vault.as_deref(),
+            token_program: &self.token_program,
+        }
+    }
+    /// Pays `payment` of token B, minus the protocol fee, to the maker and the
+    /// fee to the treasury.
+    fn transfer_to_maker(&self, payment: u64) -> Result<()> {
+        TakerPayment {
+            escrow: &self.escrow,
+            taker: &self.taker,
+            mint_b: self.mint_b.as_deref(),
+            taker_ata_b: self.taker_ata_b.as_deref(),
+            token_program: &self.token_program,
+            system_program: &self.system_program,
+        }
+        .pay_maker(
+            payment,
+            &self.maker.to_account_info(),
+            self.maker_ata_b.as_deref(),
+            self.treasury.as_ref().map(|treasury| treasury.as_ref()),
+            self.treasury_ata_b.as_deref(),
+        )
+    }
+    fn withdraw(&self, amount: u64) -> Result<()> {
+        self.deposit().withdraw(
+            &self.taker.to_account_info(),
+            self.taker_ata_a.as_deref(),
+            amount,
+        )
+    }
+    fn close_vault_and_escrow(&mut self) -> Result<()> {
+        self.deposit().close_vault(&self.maker.to_account_info())?;
+        self.escrow.close(self.maker.to_account_info())?;
+        Ok(())
+    }
+}
+
+pub fn handler(
+    ctx: Context<TakePartial>,
+    amount: u64,
+    expected_receive: u64,
+    expected_deposit: u64,
+) -> Result<()> {
+    require!(
+        !ctx.accounts.escrow.is_expired(Clock::get()?.unix_timestamp),
+        EscrowError::EscrowExpired
+    );
+    require!(
+        ctx.accounts
+            .escrow
+            .price_at_most(expected_receive, expected_deposit),
+        EscrowError::TermsChanged
+    );
+    require!(
+        !ctx.accounts.escrow.is_bundle(),
+        EscrowError::BundleNotDivisible
+    );
+    require_gt!(amount, 0, EscrowError::InvalidAmount);
+    require_gte!(
+        ctx.accounts.escrow.deposit,
+        amount,
+        EscrowError::InvalidAmount
+    );
+
+    if amount == ctx.accounts.escrow.deposit {
+        // Filling the rest works like `take`: the whole vault goes to the
+        // taker, including anything sent to it outside the escrow.
+        let receive = ctx.accounts.escrow.receive;
+        ctx.accounts.transfer_to_maker(receive)?;
+        let remaining = ctx.accounts.deposit().balance();
+        ctx.accounts.withdraw(remaining)?;
+        return ctx.accounts.close_vault_and_escrow();
+    }
+
+    // A slice whose rounded-up price is the whole of `receive` would leave
+    // the rest of the vault free to take.
+    let payment = ctx.accounts.price_of(amount)?;
+    require_gt!(
+        ctx.accounts.escrow.receive,
+        payment,
+        EscrowError::InvalidAmount
+    );
+
+    ctx.accounts.transfer_to_maker(payment)?;
+    ctx.accounts.withdraw(amount)?;
+
+    let escrow = &mut ctx.accounts.escrow;
+    escrow.receive -= payment;
+    escrow.deposit -= amount;
+    Ok(())
+}
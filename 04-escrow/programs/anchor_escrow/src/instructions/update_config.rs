@@ -0,0 +1,32 @@
+use crate::{errors::EscrowError, state::Config};
+use anchor_lang::prelude::*;
+
+/// Changes the fee, treasury or fee authority. A new `fee_bps` only applies to
+/// escrows made afterwards; pass 0 to stop charging fees on them.
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub fee_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = fee_authority @ EscrowError::InvalidFeeAuthority,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+pub fn handler(
+    ctx: Context<UpdateConfig>,
+    fee_bps: u16,
+    treasury: Pubkey,
+    fee_authority: Pubkey,
+) -> Result<()> {
+    require_gte!(Config::MAX_FEE_BPS, fee_bps, EscrowError::InvalidFee);
+
+    let config = &mut ctx.accounts.config;
+    config.fee_bps = fee_bps;
+    config.treasury = treasury;
+    config.fee_authority = fee_authority;
+    Ok(())
+}
@@ -1,23 +1,84 @@
 #![allow(unexpected_cfgs)]
 
 use anchor_lang::prelude::*;
+pub mod bundle;
 pub mod errors;
+pub mod extensions;
 pub mod instructions;
 pub use instructions::*;
 pub mod state;
+pub mod transfers;
+use state::Leg;
 
 declare_id!("8F3byNyXVHzfmjKK9J2cxvVbKzRiVYh8icoprMUqSFmb");
 
 #[program]
 pub mod anchor_escrow {
     use super::*;
-    pub fn make(ctx: Context<Make>, seed: u64, receive: u64, amount: u64) -> Result<()> {
-        instructions::make::handler(ctx, seed, receive, amount)
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        instructions::initialize_config::handler(ctx, fee_bps, treasury)
     }
-    pub fn take(ctx: Context<Take>) -> Result<()> {
-        instructions::take::handler(ctx)
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        fee_bps: u16,
+        treasury: Pubkey,
+        fee_authority: Pubkey,
+    ) -> Result<()> {
+        instructions::update_config::handler(ctx, fee_bps, treasury, fee_authority)
     }
-    pub fn refund(ctx: Context<Refund>) -> Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn make<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Make<'info>>,
+        seed: u64,
+        receive: u64,
+        amount: u64,
+        expiry: Option<i64>,
+        taker: Option<Pubkey>,
+        extra_offered: Vec<Leg>,
+        extra_requested: Vec<Leg>,
+    ) -> Result<()> {
+        instructions::make::handler(
+            ctx,
+            seed,
+            receive,
+            amount,
+            expiry,
+            taker,
+            extra_offered,
+            extra_requested,
+        )
+    }
+    pub fn take<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Take<'info>>,
+        expected_receive: u64,
+        expected_deposit: u64,
+    ) -> Result<()> {
+        instructions::take::handler(ctx, expected_receive, expected_deposit)
+    }
+    pub fn take_partial(
+        ctx: Context<TakePartial>,
+        amount: u64,
+        expected_receive: u64,
+        expected_deposit: u64,
+    ) -> Result<()> {
+        instructions::take_partial::handler(ctx, amount, expected_receive, expected_deposit)
+    }
+    pub fn refund<'info>(ctx: Context<'_, '_, 'info, 'info, Refund<'info>>) -> Result<()> {
         instructions::refund::handler(ctx)
     }
+    pub fn amend(
+        ctx: Context<Amend>,
+        receive: u64,
+        deposit: u64,
+        expiry: Option<i64>,
+    ) -> Result<()> {
+        instructions::amend::handler(ctx, receive, deposit, expiry)
+    }
+    pub fn expire<'info>(ctx: Context<'_, '_, 'info, 'info, Expire<'info>>) -> Result<()> {
+        instructions::expire::handler(ctx)
+    }
 }
\ No newline at end of file
@@ -1,12 +1,104 @@
 use anchor_lang::prelude::*;
  
+/// One side of the main trade: lamports or a token mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum Asset {
+  /// Native SOL, held directly in the escrow PDA instead of a vault.
+  Sol,
+  /// An SPL Token or Token-2022 mint.
+  Token(Pubkey),
+}
+
+impl Asset {
+  pub fn from_mint(mint: Option<Pubkey>) -> Self {
+    mint.map_or(Asset::Sol, Asset::Token)
+  }
+
+  /// Whether `mint`, the optional mint account of an instruction, is this
+  /// asset: omitted for SOL, or the asset's own mint.
+  pub fn matches(&self, mint: Option<Pubkey>) -> bool {
+    *self == Asset::from_mint(mint)
+  }
+}
+
+/// Most extra mints a bundle escrow can offer, and separately ask for.
+pub const MAX_EXTRA_LEGS: usize = 4;
+
+/// One extra mint of a bundle escrow and the amount of it that changes hands.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Leg {
+  pub mint: Pubkey,
+  pub amount: u64,
+}
+
 #[derive(InitSpace)]
 #[account(discriminator = 1)]
   pub struct Escrow {
   pub seed: u64,
   pub maker: Pubkey,
-  pub mint_a: Pubkey,
-  pub mint_b: Pubkey,
+  pub mint_a: Asset,
+  pub mint_b: Asset,
   pub receive: u64,
+  /// Token A still in the vault (or lamports held by the escrow, for a SOL
+  /// offer) for takers; `receive` is the price of all of it.
+  pub deposit: u64,
+  /// Unix timestamp from which the offer can no longer be taken.
+  pub expiry: Option<i64>,
+  /// The only wallet allowed to take the offer, for private OTC deals.
+  pub taker: Option<Pubkey>,
+  /// Protocol fee in force when the escrow was made, in basis points. Takes
+  /// charge this rate even if the config changes later.
+  pub fee_bps: u16,
+  /// Mints locked next to token A, each in its own vault owned by the escrow.
+  #[max_len(MAX_EXTRA_LEGS)]
+  pub extra_offered: Vec<Leg>,
+  /// Mints the taker must pay on top of `receive` of token B.
+  #[max_len(MAX_EXTRA_LEGS)]
+  pub extra_requested: Vec<Leg>,
   pub bump: u8,
+}
+
+impl Escrow {
+  pub fn is_expired(&self, now: i64) -> bool {
+    self.expiry.is_some_and(|expiry| now >= expiry)
+  }
+
+  pub fn is_bundle(&self) -> bool {
+    !self.extra_offered.is_empty() || !self.extra_requested.is_empty()
+  }
+
+  /// Whether the escrow still asks at most `receive` for at least `deposit`,
+  /// the terms a taker quoted, so an `amend` landing first can't worsen them.
+  pub fn offers_at_least(&self, receive: u64, deposit: u64) -> bool {
+    self.receive <= receive && self.deposit >= deposit
+  }
+
+  /// Whether token A still costs at most `receive / deposit` token B. Other
+  /// partial fills keep the price, so they don't trip this check.
+  pub fn price_at_most(&self, receive: u64, deposit: u64) -> bool {
+    self.receive as u128 * deposit as u128 <= receive as u128 * self.deposit as u128
+  }
+
+  /// Treasury's cut of a `payment`, rounded down in the taker's favour.
+  pub fn fee_for(&self, payment: u64) -> u64 {
+    (payment as u128 * self.fee_bps as u128 / 10_000) as u64
+  }
+}
+
+/// Venue-wide fee settings, a single PDA at `["config"]`.
+#[derive(InitSpace)]
+#[account(discriminator = 2)]
+pub struct Config {
+  pub fee_authority: Pubkey,
+  /// Share of every token B payment sent to the treasury, in basis points,
+  /// for escrows made from now on.
+  pub fee_bps: u16,
+  /// Wallet whose token B associated token accounts collect the fees.
+  pub treasury: Pubkey,
+  pub bump: u8,
+}
+
+impl Config {
+  /// 10%, so a compromised fee authority can't take the whole payment.
+  pub const MAX_FEE_BPS: u16 = 1_000;
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use crate::{
+    errors::EscrowError,
+    extensions::gross_up,
+    state::{Asset, Escrow},
+};
+use anchor_lang::{
+    prelude::*,
+    system_program::{transfer, Transfer},
+};
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+
+/// Owns the seeds the escrow PDA signs with, so they outlive the CPIs that
+/// borrow them.
+pub struct EscrowSigner {
+    maker: Pubkey,
+    seed: [u8; 8],
+    bump: [u8; 1],
+}
+
+impl EscrowSigner {
+    pub fn new(escrow: &Escrow) -> Self {
+        Self {
+            maker: escrow.maker,
+            seed: escrow.seed.to_le_bytes(),
+            bump: [escrow.bump],
+        }
+    }
+
+    pub fn seeds(&self) -> [&[u8]; 4] {
+        [b"escrow", self.maker.as_ref(), &self.seed, &self.bump]
+    }
+}
+
+/// Token A held by an escrow: the balance of its vault, or lamports kept in
+/// the escrow PDA on top of its rent when the maker offers SOL.
+pub struct EscrowDeposit<'a, 'info> {
+    pub escrow: &'a Account<'info, Escrow>,
+    pub mint_a: Option<&'a InterfaceAccount<'info, Mint>>,
+    pub vault: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: &'a Interface<'info, TokenInterface>,
+}
+
+impl<'info> EscrowDeposit<'_, 'info> {
+    /// Everything left for takers, including tokens sent to the vault
+    /// outside the escrow.
+    pub fn balance(&self) -> u64 {
+        self.vault.map_or(self.escrow.deposit, |vault| vault.amount)
+    }
+
+    /// Sends `amount` from the vault to `recipient_ata`, signed by the
+    /// escrow, or straight to the `recipient` wallet for a SOL offer.
+    pub fn withdraw(
+        &self,
+        recipient: &AccountInfo<'info>,
+        recipient_ata: Option<&InterfaceAccount<'info, TokenAccount>>,
+        amount: u64,
+    ) -> Result<()> {
+        if self.escrow.mint_a == Asset::Sol {
+            self.escrow.to_account_info().sub_lamports(amount)?;
+            recipient.add_lamports(amount)?;
+            return Ok(());
+        }
+        let (Some(mint_a), Some(vault), Some(recipient_ata)) =
+            (self.mint_a, self.vault, recipient_ata)
+        else {
+            return err!(EscrowError::MissingTokenAccounts);
+        };
+
+        let signer = EscrowSigner::new(self.escrow);
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.key(),
+                TransferChecked {
+                    from: vault.to_account_info(),
+                    to: recipient_ata.to_account_info(),
+                    mint: mint_a.to_account_info(),
+                    authority: self.escrow.to_account_info(),
+                },
+                &[&signer.seeds()[..]],
+            ),
+            amount,
+            mint_a.decimals,
+        )
+    }
+
+    /// Closes the vault, if there is one, with its rent going to
+    /// `rent_destination`.
+    pub fn close_vault(&self, rent_destination: &AccountInfo<'info>) -> Result<()> {
+        let Some(vault) = self.vault else {
+            return Ok(());
+        };
+        let signer = EscrowSigner::new(self.escrow);
+        close_account(CpiContext::new_with_signer(
+            self.token_program.key(),
+            CloseAccount {
+                account: vault.to_account_info(),
+                authority: self.escrow.to_account_info(),
+                destination: rent_destination.clone(),
+            },
+            &[&signer.seeds()[..]],
+        ))
+    }
+}
+
+/// Token B paid by the taker, from their token account or, when the maker
+/// asked for SOL, from their wallet.
+pub struct TakerPayment<'a, 'info> {
+    pub escrow: &'a Escrow,
+    pub taker: &'a Signer<'info>,
+    pub mint_b: Option<&'a InterfaceAccount<'info, Mint>>,
+    pub taker_ata_b: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: &'a Interface<'info, TokenInterface>,
+    pub system_program: &'a Program<'info, System>,
+}
+
+impl<'info> TakerPayment<'_, 'info> {
+    /// Pays `payment`, minus the escrow's protocol fee, to the maker and the
+    /// fee to the treasury, which is only needed when there is a fee.
+    pub fn pay_maker(
+        &self,
+        payment: u64,
+        maker: &AccountInfo<'info>,
+        maker_ata: Option<&InterfaceAccount<'info, TokenAccount>>,
+        treasury: Option<&AccountInfo<'info>>,
+        treasury_ata: Option<&InterfaceAccount<'info, TokenAccount>>,
+    ) -> Result<()> {
+        let fee = self.escrow.fee_for(payment);
+        if fee > 0 {
+            let treasury = treasury.ok_or(EscrowError::InvalidTreasury)?;
+            self.pay(treasury, treasury_ata, fee)?;
+        }
+        self.pay(maker, maker_ata, payment - fee)
+    }
+
+    /// Sends `amount` to `to_ata`, grossed up so that all of it arrives
+    /// despite a Token-2022 transfer fee, or straight to the `to` wallet when
+    /// token B is SOL.
+    fn pay(
+        &self,
+        to: &AccountInfo<'info>,
+        to_ata: Option<&InterfaceAccount<'info, TokenAccount>>,
+        amount: u64,
+    ) -> Result<()> {
+        if self.escrow.mint_b == Asset::Sol {
+            return transfer(
+                CpiContext::new(
+                    self.system_program.key(),
+                    Transfer {
+                        from: self.taker.to_account_info(),
+                        to: to.clone(),
+                    },
+                ),
+                amount,
+            );
+        }
+        let (Some(mint_b), Some(taker_ata_b), Some(to_ata)) =
+            (self.mint_b, self.taker_ata_b, to_ata)
+        else {
+            return err!(EscrowError::MissingTokenAccounts);
+        };
+        transfer_checked(
+            CpiContext::new(
+                self.token_program.key(),
+                TransferChecked {
+                    from: taker_ata_b.to_account_info(),
+                    to: to_ata.to_account_info(),
+                    mint: mint_b.to_account_info(),
+                    authority: self.taker.to_account_info(),
+                },
+            ),
+            gross_up(&mint_b.to_account_info(), amount)?,
+            mint_b.decimals,
+        )
+    }
+}
\ No newline at end of file
@@ -2,13 +2,329 @@
 
 use anchor_litesvm::{AnchorLiteSVM, Signer};
 use litesvm_utils::{AssertionHelpers, TestHelpers};
-use anchor_lang::system_program;
-use spl_associated_token_account::get_associated_token_address;
+use anchor_lang::{
+    prelude::Clock,
+    solana_program::{
+        bpf_loader_upgradeable::{self, get_program_data_address},
+        program_option::COption,
+    },
+    system_program,
+};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        non_transferable::NonTransferable,
+        transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, BaseStateWithExtensionsMut, ExtensionType, StateWithExtensions,
+        StateWithExtensionsMut,
+    },
+    state::{Account as TokenAccountState, AccountState, Mint as MintState},
+};
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Keypair,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+};
 use spl_token;
 
 // Generate client modules from the program using declare_program!
 anchor_lang::declare_program!(anchor_escrow);
 
+/// Redeploys the escrow under the upgradeable loader with `authority` as its
+/// upgrade authority, as `anchor deploy` leaves it, so that
+/// `initialize_config` can find the program data account.
+fn set_upgrade_authority(ctx: &mut AnchorLiteSVM, authority: &Pubkey) -> Pubkey {
+    let program_data = get_program_data_address(&anchor_escrow::ID);
+
+    // Bincode layout of `UpgradeableLoaderState::ProgramData` followed by
+    // the ELF, then of `UpgradeableLoaderState::Program`.
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(authority.as_ref());
+    data.extend_from_slice(include_bytes!("../target/deploy/anchor_escrow.so"));
+    ctx.svm
+        .set_account(
+            program_data,
+            Account {
+                lamports: ctx.svm.minimum_balance_for_rent_exemption(data.len()),
+                data,
+                owner: bpf_loader_upgradeable::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+
+    let mut data = 2u32.to_le_bytes().to_vec();
+    data.extend_from_slice(program_data.as_ref());
+    ctx.svm
+        .set_account(
+            anchor_escrow::ID,
+            Account {
+                lamports: ctx.svm.minimum_balance_for_rent_exemption(data.len()),
+                data,
+                owner: bpf_loader_upgradeable::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    program_data
+}
+
+/// Builds `initialize_config` signed by `fee_authority`.
+fn initialize_config_ix(
+    ctx: &mut AnchorLiteSVM,
+    fee_authority: &Pubkey,
+    fee_bps: u16,
+    treasury: Pubkey,
+) -> Instruction {
+    ctx.program()
+        .accounts(anchor_escrow::client::accounts::InitializeConfig {
+            fee_authority: *fee_authority,
+            program: anchor_escrow::ID,
+            program_data: get_program_data_address(&anchor_escrow::ID),
+            config: ctx.svm.get_pda(&[b"config"], &anchor_escrow::ID),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::InitializeConfig { fee_bps, treasury })
+        .instruction()
+        .unwrap()
+}
+
+/// Creates the protocol fee config, signed by the upgrade authority. Escrows
+/// made afterwards charge `fee_bps`. Returns the config PDA, the treasury
+/// wallet and the fee authority.
+fn initialize_config(ctx: &mut AnchorLiteSVM, fee_bps: u16) -> (Pubkey, Pubkey, Keypair) {
+    let fee_authority = ctx.svm.create_funded_account(1_000_000_000).unwrap();
+    let treasury = Keypair::new().pubkey();
+    set_upgrade_authority(ctx, &fee_authority.pubkey());
+
+    let ix = initialize_config_ix(ctx, &fee_authority.pubkey(), fee_bps, treasury);
+    ctx.execute_instruction(ix, &[&fee_authority])
+        .unwrap()
+        .assert_success();
+
+    let config = ctx.svm.get_pda(&[b"config"], &anchor_escrow::ID);
+    (config, treasury, fee_authority)
+}
+
+/// Accounts of an escrow offering 1.0 token A for 0.5 token B under a
+/// zero-fee config, with a taker holding exactly the 0.5 token B.
+struct EscrowSetup {
+    ctx: AnchorLiteSVM,
+    maker: Keypair,
+    taker: Keypair,
+    mint_a: Keypair,
+    mint_b: Keypair,
+    maker_ata_a: Pubkey,
+    taker_ata_b: Pubkey,
+    escrow: Pubkey,
+    vault: Pubkey,
+    config: Pubkey,
+    treasury: Pubkey,
+}
+
+/// Deploys the program and makes the escrow described on `EscrowSetup`.
+/// `expires_in` is in seconds from now, and `designated_taker` makes the
+/// setup's taker the only one allowed to fill it.
+fn setup_escrow(seed: u64, expires_in: Option<i64>, designated_taker: bool) -> EscrowSetup {
+    let mut ctx = AnchorLiteSVM::build_with_program(
+        anchor_escrow::ID,
+        include_bytes!("../target/deploy/anchor_escrow.so"),
+    );
+    let (config, treasury, _) = initialize_config(&mut ctx, 0);
+
+    let maker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let taker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let mint_a = ctx.svm.create_token_mint(&maker, 9).unwrap();
+    let mint_b = ctx.svm.create_token_mint(&maker, 9).unwrap();
+
+    let maker_ata_a = ctx.svm
+        .create_associated_token_account(&mint_a.pubkey(), &maker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_a.pubkey(), &maker_ata_a, &maker, 1_000_000_000)
+        .unwrap();
+    let taker_ata_b = ctx.svm
+        .create_associated_token_account(&mint_b.pubkey(), &taker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_b.pubkey(), &taker_ata_b, &maker, 500_000_000)
+        .unwrap();
+
+    let clock: Clock = ctx.svm.get_sysvar();
+    let escrow = ctx.svm.get_pda(
+        &[b"escrow", maker.pubkey().as_ref(), &seed.to_le_bytes()],
+        &anchor_escrow::ID,
+    );
+    let vault = get_associated_token_address(&escrow, &mint_a.pubkey());
+
+    let make_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Make {
+            maker: maker.pubkey(),
+            escrow,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            maker_ata_a: Some(maker_ata_a),
+            vault: Some(vault),
+            config,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Make {
+            seed,
+            receive: 500_000_000,
+            amount: 1_000_000_000,
+            expiry: expires_in.map(|seconds| clock.unix_timestamp + seconds),
+            taker: designated_taker.then(|| taker.pubkey()),
+            extra_offered: vec![],
+            extra_requested: vec![],
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(make_ix, &[&maker])
+        .unwrap()
+        .assert_success();
+
+    EscrowSetup {
+        ctx,
+        maker,
+        taker,
+        mint_a,
+        mint_b,
+        maker_ata_a,
+        taker_ata_b,
+        escrow,
+        vault,
+        config,
+        treasury,
+    }
+}
+
+/// Writes a Token-2022 mint with at most one extension: a 1% transfer fee, or
+/// `NonTransferable`.
+fn set_token_2022_mint(
+    ctx: &mut AnchorLiteSVM,
+    authority: &Pubkey,
+    extension: Option<ExtensionType>,
+) -> Pubkey {
+    let extensions: Vec<ExtensionType> = extension.into_iter().collect();
+    let mut data =
+        vec![0; ExtensionType::try_calculate_account_len::<MintState>(&extensions).unwrap()];
+    let mut state = StateWithExtensionsMut::<MintState>::unpack_uninitialized(&mut data).unwrap();
+    match extension {
+        Some(ExtensionType::TransferFeeConfig) => {
+            let fee = TransferFee {
+                epoch: 0.into(),
+                maximum_fee: u64::MAX.into(),
+                transfer_fee_basis_points: 100.into(),
+            };
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.older_transfer_fee = fee;
+            config.newer_transfer_fee = fee;
+        }
+        Some(ExtensionType::NonTransferable) => {
+            state.init_extension::<NonTransferable>(true).unwrap();
+        }
+        _ => {}
+    }
+    state.base = MintState {
+        mint_authority: COption::Some(*authority),
+        supply: 0,
+        decimals: 9,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    state.pack_base();
+    if !extensions.is_empty() {
+        state.init_account_type().unwrap();
+    }
+
+    let mint = Keypair::new().pubkey();
+    ctx.svm
+        .set_account(
+            mint,
+            Account {
+                lamports: 10_000_000,
+                data,
+                owner: spl_token_2022::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    mint
+}
+
+/// Writes `owner`'s Token-2022 associated token account holding `amount`.
+/// Accounts of a transfer-fee mint need the `TransferFeeAmount` extension.
+fn set_token_2022_ata(
+    ctx: &mut AnchorLiteSVM,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+    with_fee: bool,
+) -> Pubkey {
+    let extensions = if with_fee {
+        vec![ExtensionType::TransferFeeAmount]
+    } else {
+        vec![]
+    };
+    let mut data = vec![
+        0;
+        ExtensionType::try_calculate_account_len::<TokenAccountState>(&extensions).unwrap()
+    ];
+    let mut state =
+        StateWithExtensionsMut::<TokenAccountState>::unpack_uninitialized(&mut data).unwrap();
+    if with_fee {
+        state.init_extension::<TransferFeeAmount>(true).unwrap();
+    }
+    state.base = TokenAccountState {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    };
+    state.pack_base();
+    if with_fee {
+        state.init_account_type().unwrap();
+    }
+
+    let ata = get_associated_token_address_with_program_id(owner, mint, &spl_token_2022::ID);
+    ctx.svm
+        .set_account(
+            ata,
+            Account {
+                lamports: 10_000_000,
+                data,
+                owner: spl_token_2022::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    ata
+}
+
+fn token_2022_balance(ctx: &AnchorLiteSVM, account: &Pubkey) -> u64 {
+    let account = ctx.svm.get_account(account).unwrap();
+    StateWithExtensions::<TokenAccountState>::unpack(&account.data)
+        .unwrap()
+        .base
+        .amount
+}
+
 #[test]
 fn test_escrow_make_and_take() {
     // ============================================================================
@@ -63,10 +379,12 @@ fn test_escrow_make_and_take() {
         .accounts(anchor_escrow::client::accounts::Make {
             maker: maker.pubkey(),
             escrow: escrow_pda,
-            mint_a: mint_a.pubkey(),
-            mint_b: mint_b.pubkey(),
-            maker_ata_a,
-            vault,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            maker_ata_a: Some(maker_ata_a),
+            vault: Some(vault),
+            // Not initialized yet, so the escrow charges no fee
+            config: ctx.svm.get_pda(&[b"config"], &program_id),
             associated_token_program: spl_associated_token_account::id(),
             token_program: spl_token::id(),
             system_program: system_program::ID,
@@ -75,6 +393,10 @@ fn test_escrow_make_and_take() {
             seed,
             receive: 500_000_000,  // 0.5 tokens
             amount: 1_000_000_000, // 1.0 tokens
+            expiry: None,
+            taker: None,
+            extra_offered: vec![],
+            extra_requested: vec![],
         })
         .instruction()
         .unwrap();
@@ -99,17 +421,24 @@ fn test_escrow_make_and_take() {
             taker: taker.pubkey(),
             maker: maker.pubkey(),
             escrow: escrow_pda,
-            mint_a: mint_a.pubkey(),
-            mint_b: mint_b.pubkey(),
-            vault,
-            taker_ata_a,
-            taker_ata_b,
-            maker_ata_b,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            vault: Some(vault),
+            taker_ata_a: Some(taker_ata_a),
+            taker_ata_b: Some(taker_ata_b),
+            maker_ata_b: Some(maker_ata_b),
+            // No config exists, so the escrow charges no fee
+            config: None,
+            treasury: None,
+            treasury_ata_b: None,
             associated_token_program: spl_associated_token_account::id(),
             token_program: spl_token::id(),
             system_program: system_program::ID,
         })
-        .args(anchor_escrow::client::args::Take {})
+        .args(anchor_escrow::client::args::Take {
+            expected_receive: 500_000_000,
+            expected_deposit: 1_000_000_000,
+        })
         .instruction()
         .unwrap();
 
@@ -130,3 +459,1047 @@ fn test_escrow_make_and_take() {
     ctx.svm.assert_token_balance(&taker_ata_b, 0);             // Taker sent all mint_b tokens
     ctx.svm.assert_token_balance(&maker_ata_b, 500_000_000);   // Maker received mint_b tokens
 }
+
+#[test]
+fn test_escrow_take_partial() {
+    // Offer 1.0 token A for 0.5 token B.
+    let EscrowSetup {
+        mut ctx,
+        maker,
+        taker,
+        mint_a,
+        mint_b,
+        taker_ata_b,
+        escrow: escrow_pda,
+        vault,
+        config,
+        treasury,
+        ..
+    } = setup_escrow(7, None, false);
+
+    let taker_ata_a = get_associated_token_address(&taker.pubkey(), &mint_a.pubkey());
+    let maker_ata_b = get_associated_token_address(&maker.pubkey(), &mint_b.pubkey());
+    let take_partial_ix = |ctx: &mut AnchorLiteSVM, amount: u64, expected_receive: u64| {
+        ctx.program()
+            .accounts(anchor_escrow::client::accounts::TakePartial {
+                taker: taker.pubkey(),
+                maker: maker.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a.pubkey()),
+                mint_b: Some(mint_b.pubkey()),
+                vault: Some(vault),
+                taker_ata_a: Some(taker_ata_a),
+                taker_ata_b: Some(taker_ata_b),
+                maker_ata_b: Some(maker_ata_b),
+                config: Some(config),
+                treasury: Some(treasury),
+                treasury_ata_b: Some(get_associated_token_address(&treasury, &mint_b.pubkey())),
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::TakePartial {
+                amount,
+                expected_receive,
+                expected_deposit: 1_000_000_000,
+            })
+            .instruction()
+            .unwrap()
+    };
+
+    // Buying a quarter of the vault costs a quarter of the price.
+    let ix = take_partial_ix(&mut ctx, 250_000_000, 500_000_000);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_success();
+    assert!(ctx.account_exists(&escrow_pda), "Escrow should stay open");
+    ctx.svm.assert_token_balance(&vault, 750_000_000);
+    ctx.svm.assert_token_balance(&taker_ata_a, 250_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_b, 125_000_000);
+
+    // Taking more than what is left fails.
+    let ix = take_partial_ix(&mut ctx, 750_000_001, 500_000_000);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    // A quote at a lower price than the escrow's fails.
+    let ix = take_partial_ix(&mut ctx, 750_000_000, 499_999_999);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    // Filling the rest pays the remaining price and closes the escrow. The
+    // first fill kept the price, so the original quote still holds.
+    let ix = take_partial_ix(&mut ctx, 750_000_000, 500_000_000);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_account_closed(&escrow_pda);
+    ctx.svm.assert_account_closed(&vault);
+    ctx.svm.assert_token_balance(&taker_ata_a, 1_000_000_000);
+    ctx.svm.assert_token_balance(&taker_ata_b, 0);
+    ctx.svm.assert_token_balance(&maker_ata_b, 500_000_000);
+}
+
+#[test]
+fn test_escrow_expire() {
+    // The offer expires an hour from now.
+    let EscrowSetup {
+        mut ctx,
+        maker,
+        taker,
+        mint_a,
+        mint_b,
+        maker_ata_a,
+        taker_ata_b,
+        escrow: escrow_pda,
+        vault,
+        config,
+        treasury,
+    } = setup_escrow(9, Some(3_600), false);
+    let cranker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let mut clock: Clock = ctx.svm.get_sysvar();
+    let expiry = clock.unix_timestamp + 3_600;
+
+    let expire_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Expire {
+            cranker: cranker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: escrow_pda,
+            mint_a: Some(mint_a.pubkey()),
+            vault: Some(vault),
+            maker_ata_a: Some(maker_ata_a),
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Expire {})
+        .instruction()
+        .unwrap();
+
+    // Nobody can expire a live offer.
+    ctx.execute_instruction(expire_ix.clone(), &[&cranker])
+        .unwrap()
+        .assert_failure();
+
+    clock.unix_timestamp = expiry;
+    ctx.svm.set_sysvar(&clock);
+    ctx.svm.expire_blockhash();
+
+    // Once expired the offer cannot be taken...
+    let take_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Take {
+            taker: taker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: escrow_pda,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            vault: Some(vault),
+            taker_ata_a: Some(get_associated_token_address(&taker.pubkey(), &mint_a.pubkey())),
+            taker_ata_b: Some(taker_ata_b),
+            maker_ata_b: Some(get_associated_token_address(&maker.pubkey(), &mint_b.pubkey())),
+            config: Some(config),
+            treasury: Some(treasury),
+            treasury_ata_b: Some(get_associated_token_address(&treasury, &mint_b.pubkey())),
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Take {
+            expected_receive: 500_000_000,
+            expected_deposit: 1_000_000_000,
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(take_ix, &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    // ...and anyone can return the tokens to the maker for the escrow's rent.
+    let cranker_balance = ctx.svm.get_balance(&cranker.pubkey()).unwrap();
+    ctx.execute_instruction(expire_ix, &[&cranker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_account_closed(&escrow_pda);
+    ctx.svm.assert_account_closed(&vault);
+    ctx.svm.assert_token_balance(&maker_ata_a, 1_000_000_000);
+    assert!(
+        ctx.svm.get_balance(&cranker.pubkey()).unwrap() > cranker_balance,
+        "Cranker should earn the escrow rent"
+    );
+}
+
+#[test]
+fn test_escrow_designated_taker() {
+    // Only `taker` may fill this offer.
+    let EscrowSetup {
+        mut ctx,
+        maker,
+        taker,
+        mint_a,
+        mint_b,
+        taker_ata_b,
+        escrow: escrow_pda,
+        vault,
+        config,
+        treasury,
+        ..
+    } = setup_escrow(11, None, true);
+    let outsider = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let outsider_ata_b = ctx.svm
+        .create_associated_token_account(&mint_b.pubkey(), &outsider)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_b.pubkey(), &outsider_ata_b, &maker, 500_000_000)
+        .unwrap();
+
+    let maker_ata_b = get_associated_token_address(&maker.pubkey(), &mint_b.pubkey());
+    let take_ix = |ctx: &mut AnchorLiteSVM, taker: &Keypair, taker_ata_b: Pubkey| {
+        ctx.program()
+            .accounts(anchor_escrow::client::accounts::Take {
+                taker: taker.pubkey(),
+                maker: maker.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a.pubkey()),
+                mint_b: Some(mint_b.pubkey()),
+                vault: Some(vault),
+                taker_ata_a: Some(get_associated_token_address(&taker.pubkey(), &mint_a.pubkey())),
+                taker_ata_b: Some(taker_ata_b),
+                maker_ata_b: Some(maker_ata_b),
+                config: Some(config),
+                treasury: Some(treasury),
+                treasury_ata_b: Some(get_associated_token_address(&treasury, &mint_b.pubkey())),
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::Take {
+                expected_receive: 500_000_000,
+                expected_deposit: 1_000_000_000,
+            })
+            .instruction()
+            .unwrap()
+    };
+
+    let ix = take_ix(&mut ctx, &outsider, outsider_ata_b);
+    ctx.execute_instruction(ix, &[&outsider])
+        .unwrap()
+        .assert_failure();
+    ctx.svm.assert_token_balance(&vault, 1_000_000_000);
+
+    let ix = take_ix(&mut ctx, &taker, taker_ata_b);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_account_closed(&escrow_pda);
+    ctx.svm.assert_token_balance(&maker_ata_b, 500_000_000);
+}
+
+#[test]
+fn test_escrow_config_authority() {
+    let mut ctx = AnchorLiteSVM::build_with_program(
+        anchor_escrow::ID,
+        include_bytes!("../target/deploy/anchor_escrow.so"),
+    );
+    let upgrade_authority = ctx.svm.create_funded_account(1_000_000_000).unwrap();
+    let stranger = ctx.svm.create_funded_account(1_000_000_000).unwrap();
+    let treasury = Keypair::new().pubkey();
+    set_upgrade_authority(&mut ctx, &upgrade_authority.pubkey());
+
+    // Nobody but the upgrade authority can claim the fee config.
+    let ix = initialize_config_ix(&mut ctx, &stranger.pubkey(), 100, treasury);
+    ctx.execute_instruction(ix, &[&stranger])
+        .unwrap()
+        .assert_failure();
+
+    // The fee is capped at 1000 bps.
+    let ix = initialize_config_ix(&mut ctx, &upgrade_authority.pubkey(), 1_001, treasury);
+    ctx.execute_instruction(ix, &[&upgrade_authority])
+        .unwrap()
+        .assert_failure();
+
+    let ix = initialize_config_ix(&mut ctx, &upgrade_authority.pubkey(), 1_000, treasury);
+    ctx.execute_instruction(ix, &[&upgrade_authority])
+        .unwrap()
+        .assert_success();
+}
+
+#[test]
+fn test_escrow_protocol_fee() {
+    let program_id = anchor_escrow::ID;
+    let mut ctx = AnchorLiteSVM::build_with_program(
+        program_id,
+        include_bytes!("../target/deploy/anchor_escrow.so"),
+    );
+    // 1% of every token B payment goes to the treasury.
+    let (config, treasury, fee_authority) = initialize_config(&mut ctx, 100);
+
+    let maker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let taker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let mint_a = ctx.svm.create_token_mint(&maker, 9).unwrap();
+    let mint_b = ctx.svm.create_token_mint(&maker, 9).unwrap();
+
+    let maker_ata_a = ctx.svm
+        .create_associated_token_account(&mint_a.pubkey(), &maker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_a.pubkey(), &maker_ata_a, &maker, 2_000_000_000)
+        .unwrap();
+    let taker_ata_b = ctx.svm
+        .create_associated_token_account(&mint_b.pubkey(), &taker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_b.pubkey(), &taker_ata_b, &maker, 1_500_000_000)
+        .unwrap();
+
+    let maker_ata_b = get_associated_token_address(&maker.pubkey(), &mint_b.pubkey());
+    let treasury_ata_b = get_associated_token_address(&treasury, &mint_b.pubkey());
+    let escrow_and_vault = |ctx: &AnchorLiteSVM, seed: u64| {
+        let escrow_pda = ctx.svm.get_pda(
+            &[b"escrow", maker.pubkey().as_ref(), &seed.to_le_bytes()],
+            &program_id,
+        );
+        (escrow_pda, get_associated_token_address(&escrow_pda, &mint_a.pubkey()))
+    };
+
+    // Offers 1.0 token A for 0.5 token B, at the fee currently configured.
+    let make_ix = |ctx: &mut AnchorLiteSVM, seed: u64| {
+        let (escrow_pda, vault) = escrow_and_vault(ctx, seed);
+        ctx.program()
+            .accounts(anchor_escrow::client::accounts::Make {
+                maker: maker.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a.pubkey()),
+                mint_b: Some(mint_b.pubkey()),
+                maker_ata_a: Some(maker_ata_a),
+                vault: Some(vault),
+                config,
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::Make {
+                seed,
+                receive: 500_000_000,
+                amount: 1_000_000_000,
+                expiry: None,
+                taker: None,
+                extra_offered: vec![],
+                extra_requested: vec![],
+            })
+            .instruction()
+            .unwrap()
+    };
+    let make = |ctx: &mut AnchorLiteSVM, seed: u64| {
+        let ix = make_ix(ctx, seed);
+        ctx.execute_instruction(ix, &[&maker])
+            .unwrap()
+            .assert_success();
+    };
+    let take = |ctx: &mut AnchorLiteSVM, seed: u64| {
+        let (escrow_pda, vault) = escrow_and_vault(ctx, seed);
+        let take_ix = ctx.program()
+            .accounts(anchor_escrow::client::accounts::Take {
+                taker: taker.pubkey(),
+                maker: maker.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a.pubkey()),
+                mint_b: Some(mint_b.pubkey()),
+                vault: Some(vault),
+                taker_ata_a: Some(get_associated_token_address(&taker.pubkey(), &mint_a.pubkey())),
+                taker_ata_b: Some(taker_ata_b),
+                maker_ata_b: Some(maker_ata_b),
+                config: Some(config),
+                treasury: Some(treasury),
+                treasury_ata_b: Some(treasury_ata_b),
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::Take {
+                expected_receive: 500_000_000,
+                expected_deposit: 1_000_000_000,
+            })
+            .instruction()
+            .unwrap();
+        ctx.execute_instruction(take_ix, &[&taker])
+            .unwrap()
+            .assert_success();
+    };
+
+    make(&mut ctx, 1);
+    take(&mut ctx, 1);
+    ctx.svm.assert_token_balance(&taker_ata_b, 1_000_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_b, 495_000_000);
+    ctx.svm.assert_token_balance(&treasury_ata_b, 5_000_000);
+
+    // Leaving the config out, as an omitted optional account would be, is
+    // rejected instead of making a fee-free escrow.
+    let mut ix = make_ix(&mut ctx, 4);
+    for meta in ix.accounts.iter_mut().filter(|meta| meta.pubkey == config) {
+        meta.pubkey = program_id;
+    }
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_failure();
+
+    // Only the fee authority can change the fee.
+    let update_config_ix = |ctx: &mut AnchorLiteSVM, signer: &Keypair, fee_bps: u16| {
+        ctx.program()
+            .accounts(anchor_escrow::client::accounts::UpdateConfig {
+                fee_authority: signer.pubkey(),
+                config,
+            })
+            .args(anchor_escrow::client::args::UpdateConfig {
+                fee_bps,
+                treasury,
+                fee_authority: fee_authority.pubkey(),
+            })
+            .instruction()
+            .unwrap()
+    };
+    let ix = update_config_ix(&mut ctx, &maker, 0);
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_failure();
+
+    // The fee is capped at 10%.
+    let ix = update_config_ix(&mut ctx, &fee_authority, 1_001);
+    ctx.execute_instruction(ix, &[&fee_authority])
+        .unwrap()
+        .assert_failure();
+
+    // An escrow made before the fee is zeroed still charges 1% when taken.
+    make(&mut ctx, 2);
+    let ix = update_config_ix(&mut ctx, &fee_authority, 0);
+    ctx.execute_instruction(ix, &[&fee_authority])
+        .unwrap()
+        .assert_success();
+    take(&mut ctx, 2);
+    ctx.svm.assert_token_balance(&taker_ata_b, 500_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_b, 990_000_000);
+    ctx.svm.assert_token_balance(&treasury_ata_b, 10_000_000);
+
+    // One made afterwards pays the maker in full.
+    make(&mut ctx, 3);
+    take(&mut ctx, 3);
+    ctx.svm.assert_token_balance(&taker_ata_b, 0);
+    ctx.svm.assert_token_balance(&maker_ata_b, 1_490_000_000);
+    ctx.svm.assert_token_balance(&treasury_ata_b, 10_000_000);
+}
+
+#[test]
+fn test_escrow_bundle() {
+    let program_id = anchor_escrow::ID;
+    let mut ctx = AnchorLiteSVM::build_with_program(
+        program_id,
+        include_bytes!("../target/deploy/anchor_escrow.so"),
+    );
+    let (config, treasury, _) = initialize_config(&mut ctx, 0);
+
+    let maker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let taker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let mint_a = ctx.svm.create_token_mint(&maker, 9).unwrap();
+    let mint_b = ctx.svm.create_token_mint(&maker, 9).unwrap();
+    // Offered next to token A.
+    let mint_c = ctx.svm.create_token_mint(&maker, 6).unwrap();
+    // Asked for next to token B.
+    let mint_d = ctx.svm.create_token_mint(&maker, 6).unwrap();
+
+    let maker_ata_a = ctx.svm
+        .create_associated_token_account(&mint_a.pubkey(), &maker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_a.pubkey(), &maker_ata_a, &maker, 1_000_000_000)
+        .unwrap();
+    let maker_ata_c = ctx.svm
+        .create_associated_token_account(&mint_c.pubkey(), &maker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_c.pubkey(), &maker_ata_c, &maker, 2_000_000)
+        .unwrap();
+    let taker_ata_b = ctx.svm
+        .create_associated_token_account(&mint_b.pubkey(), &taker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_b.pubkey(), &taker_ata_b, &maker, 500_000_000)
+        .unwrap();
+    let taker_ata_d = ctx.svm
+        .create_associated_token_account(&mint_d.pubkey(), &taker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint_d.pubkey(), &taker_ata_d, &maker, 3_000_000)
+        .unwrap();
+
+    let seed: u64 = 7;
+    let escrow_pda = ctx.svm.get_pda(
+        &[b"escrow", maker.pubkey().as_ref(), &seed.to_le_bytes()],
+        &program_id,
+    );
+    let vault = get_associated_token_address(&escrow_pda, &mint_a.pubkey());
+    let vault_c = get_associated_token_address(&escrow_pda, &mint_c.pubkey());
+
+    // Offers 1.0 A + 2.0 C for 0.5 B + 3.0 D.
+    let mut make_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Make {
+            maker: maker.pubkey(),
+            escrow: escrow_pda,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            maker_ata_a: Some(maker_ata_a),
+            vault: Some(vault),
+            config,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Make {
+            seed,
+            receive: 500_000_000,
+            amount: 1_000_000_000,
+            expiry: None,
+            taker: None,
+            extra_offered: vec![anchor_escrow::types::Leg {
+                mint: mint_c.pubkey(),
+                amount: 2_000_000,
+            }],
+            extra_requested: vec![anchor_escrow::types::Leg {
+                mint: mint_d.pubkey(),
+                amount: 3_000_000,
+            }],
+        })
+        .instruction()
+        .unwrap();
+    make_ix.accounts.extend([
+        AccountMeta::new_readonly(mint_c.pubkey(), false),
+        AccountMeta::new(maker_ata_c, false),
+        AccountMeta::new(vault_c, false),
+    ]);
+    ctx.execute_instruction(make_ix, &[&maker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_token_balance(&vault, 1_000_000_000);
+    ctx.svm.assert_token_balance(&vault_c, 2_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_c, 0);
+
+    let take_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Take {
+            taker: taker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: escrow_pda,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            vault: Some(vault),
+            taker_ata_a: Some(get_associated_token_address(&taker.pubkey(), &mint_a.pubkey())),
+            taker_ata_b: Some(taker_ata_b),
+            maker_ata_b: Some(get_associated_token_address(&maker.pubkey(), &mint_b.pubkey())),
+            config: Some(config),
+            treasury: Some(treasury),
+            treasury_ata_b: Some(get_associated_token_address(&treasury, &mint_b.pubkey())),
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Take {
+            expected_receive: 500_000_000,
+            expected_deposit: 1_000_000_000,
+        })
+        .instruction()
+        .unwrap();
+
+    // Taking without the extra legs' accounts fails.
+    ctx.execute_instruction(take_ix.clone(), &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    // Bundles can't be split.
+    let take_partial_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::TakePartial {
+            taker: taker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: escrow_pda,
+            mint_a: Some(mint_a.pubkey()),
+            mint_b: Some(mint_b.pubkey()),
+            vault: Some(vault),
+            taker_ata_a: Some(get_associated_token_address(&taker.pubkey(), &mint_a.pubkey())),
+            taker_ata_b: Some(taker_ata_b),
+            maker_ata_b: Some(get_associated_token_address(&maker.pubkey(), &mint_b.pubkey())),
+            config: Some(config),
+            treasury: Some(treasury),
+            treasury_ata_b: Some(get_associated_token_address(&treasury, &mint_b.pubkey())),
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::TakePartial {
+            amount: 500_000_000,
+            expected_receive: 500_000_000,
+            expected_deposit: 1_000_000_000,
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(take_partial_ix, &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    let taker_ata_c = get_associated_token_address(&taker.pubkey(), &mint_c.pubkey());
+    let maker_ata_d = get_associated_token_address(&maker.pubkey(), &mint_d.pubkey());
+    let mut take_ix = take_ix;
+    take_ix.accounts.extend([
+        // Offered legs: [mint, vault, taker_ata].
+        AccountMeta::new_readonly(mint_c.pubkey(), false),
+        AccountMeta::new(vault_c, false),
+        AccountMeta::new(taker_ata_c, false),
+        // Requested legs: [mint, taker_ata, maker_ata, treasury_ata].
+        AccountMeta::new_readonly(mint_d.pubkey(), false),
+        AccountMeta::new(taker_ata_d, false),
+        AccountMeta::new(maker_ata_d, false),
+        AccountMeta::new(get_associated_token_address(&treasury, &mint_d.pubkey()), false),
+    ]);
+    ctx.execute_instruction(take_ix, &[&taker])
+        .unwrap()
+        .assert_success();
+
+    ctx.svm.assert_account_closed(&escrow_pda);
+    ctx.svm.assert_account_closed(&vault);
+    ctx.svm.assert_account_closed(&vault_c);
+    ctx.svm.assert_token_balance(&taker_ata_c, 2_000_000);
+    ctx.svm.assert_token_balance(&taker_ata_d, 0);
+    ctx.svm.assert_token_balance(&maker_ata_d, 3_000_000);
+}
+
+#[test]
+fn test_escrow_native_sol() {
+    let program_id = anchor_escrow::ID;
+    let mut ctx = AnchorLiteSVM::build_with_program(
+        program_id,
+        include_bytes!("../target/deploy/anchor_escrow.so"),
+    );
+    let (config, treasury, _) = initialize_config(&mut ctx, 0);
+
+    let maker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let taker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let mint = ctx.svm.create_token_mint(&maker, 9).unwrap();
+
+    let maker_ata = ctx.svm
+        .create_associated_token_account(&mint.pubkey(), &maker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint.pubkey(), &maker_ata, &maker, 1_000_000_000)
+        .unwrap();
+    let taker_ata = ctx.svm
+        .create_associated_token_account(&mint.pubkey(), &taker)
+        .unwrap();
+    ctx.svm
+        .mint_to(&mint.pubkey(), &taker_ata, &maker, 500_000_000)
+        .unwrap();
+
+    let escrow_pda = |seed: u64| {
+        Pubkey::find_program_address(
+            &[b"escrow", maker.pubkey().as_ref(), &seed.to_le_bytes()],
+            &program_id,
+        )
+        .0
+    };
+
+    // 1. Offer 1 SOL for 0.5 tokens. The SOL side passes no token accounts.
+    let sol_for_tokens = escrow_pda(1);
+    let make_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Make {
+            maker: maker.pubkey(),
+            escrow: sol_for_tokens,
+            mint_a: None,
+            mint_b: Some(mint.pubkey()),
+            maker_ata_a: None,
+            vault: None,
+            config,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Make {
+            seed: 1,
+            receive: 500_000_000,
+            amount: 1_000_000_000,
+            expiry: None,
+            taker: None,
+            extra_offered: vec![],
+            extra_requested: vec![],
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(make_ix, &[&maker])
+        .unwrap()
+        .assert_success();
+
+    let taker_balance = ctx.svm.get_balance(&taker.pubkey()).unwrap();
+    let take_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Take {
+            taker: taker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: sol_for_tokens,
+            mint_a: None,
+            mint_b: Some(mint.pubkey()),
+            vault: None,
+            taker_ata_a: None,
+            taker_ata_b: Some(taker_ata),
+            maker_ata_b: Some(maker_ata),
+            config: Some(config),
+            treasury: Some(treasury),
+            treasury_ata_b: Some(get_associated_token_address(&treasury, &mint.pubkey())),
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Take {
+            expected_receive: 500_000_000,
+            expected_deposit: 1_000_000_000,
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(take_ix, &[&taker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_account_closed(&sol_for_tokens);
+    ctx.svm.assert_token_balance(&maker_ata, 500_000_000);
+    ctx.svm.assert_token_balance(&taker_ata, 0);
+    // The taker got the SOL, less fees and the treasury ATA's rent.
+    assert!(ctx.svm.get_balance(&taker.pubkey()).unwrap() > taker_balance + 990_000_000);
+
+    // 2. Offer 1 token for 2 SOL.
+    let tokens_for_sol = escrow_pda(2);
+    let vault = get_associated_token_address(&tokens_for_sol, &mint.pubkey());
+    let make_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Make {
+            maker: maker.pubkey(),
+            escrow: tokens_for_sol,
+            mint_a: Some(mint.pubkey()),
+            mint_b: None,
+            maker_ata_a: Some(maker_ata),
+            vault: Some(vault),
+            config,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Make {
+            seed: 2,
+            receive: 2_000_000_000,
+            amount: 500_000_000,
+            expiry: None,
+            taker: None,
+            extra_offered: vec![],
+            extra_requested: vec![],
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(make_ix, &[&maker])
+        .unwrap()
+        .assert_success();
+
+    let maker_balance = ctx.svm.get_balance(&maker.pubkey()).unwrap();
+    let take_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Take {
+            taker: taker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: tokens_for_sol,
+            mint_a: Some(mint.pubkey()),
+            mint_b: None,
+            vault: Some(vault),
+            taker_ata_a: Some(taker_ata),
+            taker_ata_b: None,
+            maker_ata_b: None,
+            config: Some(config),
+            treasury: Some(treasury),
+            treasury_ata_b: None,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Take {
+            expected_receive: 2_000_000_000,
+            expected_deposit: 500_000_000,
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(take_ix, &[&taker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_account_closed(&tokens_for_sol);
+    ctx.svm.assert_account_closed(&vault);
+    ctx.svm.assert_token_balance(&taker_ata, 500_000_000);
+    // The 2 SOL payment plus the escrow and vault rent.
+    assert!(ctx.svm.get_balance(&maker.pubkey()).unwrap() > maker_balance + 2_000_000_000);
+
+    // 3. A SOL offer is refunded straight back to the maker.
+    let refunded = escrow_pda(3);
+    let make_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Make {
+            maker: maker.pubkey(),
+            escrow: refunded,
+            mint_a: None,
+            mint_b: Some(mint.pubkey()),
+            maker_ata_a: None,
+            vault: None,
+            config,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Make {
+            seed: 3,
+            receive: 500_000_000,
+            amount: 3_000_000_000,
+            expiry: None,
+            taker: None,
+            extra_offered: vec![],
+            extra_requested: vec![],
+        })
+        .instruction()
+        .unwrap();
+    let maker_balance = ctx.svm.get_balance(&maker.pubkey()).unwrap();
+    ctx.execute_instruction(make_ix, &[&maker])
+        .unwrap()
+        .assert_success();
+    assert!(ctx.svm.get_balance(&maker.pubkey()).unwrap() < maker_balance - 3_000_000_000);
+
+    let refund_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Refund {
+            maker: maker.pubkey(),
+            escrow: refunded,
+            mint_a: None,
+            vault: None,
+            maker_ata_a: None,
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Refund {})
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(refund_ix, &[&maker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_account_closed(&refunded);
+    // Only the two transaction fees are gone.
+    assert!(ctx.svm.get_balance(&maker.pubkey()).unwrap() >= maker_balance - 20_000);
+}
+
+#[test]
+fn test_escrow_token_2022_transfer_fee() {
+    let program_id = anchor_escrow::ID;
+    let mut ctx = AnchorLiteSVM::build_with_program(
+        program_id,
+        include_bytes!("../target/deploy/anchor_escrow.so"),
+    );
+    let (config, treasury, _) = initialize_config(&mut ctx, 0);
+
+    let maker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+    let taker = ctx.svm.create_funded_account(10_000_000_000).unwrap();
+
+    // Both mints charge a 1% transfer fee.
+    let mint_a = set_token_2022_mint(&mut ctx, &maker.pubkey(), Some(ExtensionType::TransferFeeConfig));
+    let mint_b = set_token_2022_mint(&mut ctx, &maker.pubkey(), Some(ExtensionType::TransferFeeConfig));
+    let maker_ata_a = set_token_2022_ata(&mut ctx, &mint_a, &maker.pubkey(), 1_000_000_000, true);
+    let taker_ata_b = set_token_2022_ata(&mut ctx, &mint_b, &taker.pubkey(), 1_000_000_000, true);
+
+    let make_ix = |ctx: &mut AnchorLiteSVM, seed: u64, mint_a: Pubkey, maker_ata_a: Pubkey| {
+        let escrow_pda = Pubkey::find_program_address(
+            &[b"escrow", maker.pubkey().as_ref(), &seed.to_le_bytes()],
+            &program_id,
+        )
+        .0;
+        let vault =
+            get_associated_token_address_with_program_id(&escrow_pda, &mint_a, &spl_token_2022::ID);
+        let ix = ctx.program()
+            .accounts(anchor_escrow::client::accounts::Make {
+                maker: maker.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a),
+                mint_b: Some(mint_b),
+                maker_ata_a: Some(maker_ata_a),
+                vault: Some(vault),
+                config,
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token_2022::ID,
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::Make {
+                seed,
+                receive: 500_000_000,
+                amount: 1_000_000_000,
+                expiry: None,
+                taker: None,
+                extra_offered: vec![],
+                extra_requested: vec![],
+            })
+            .instruction()
+            .unwrap();
+        (ix, escrow_pda, vault)
+    };
+
+    // The vault only receives 99% of the deposit, and that is what the
+    // escrow records.
+    let (ix, escrow_pda, vault) = make_ix(&mut ctx, 1, mint_a, maker_ata_a);
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_success();
+    assert_eq!(token_2022_balance(&ctx, &vault), 990_000_000);
+
+    let ata = |owner: &Pubkey, mint: &Pubkey| {
+        get_associated_token_address_with_program_id(owner, mint, &spl_token_2022::ID)
+    };
+    let take_ix = ctx.program()
+        .accounts(anchor_escrow::client::accounts::Take {
+            taker: taker.pubkey(),
+            maker: maker.pubkey(),
+            escrow: escrow_pda,
+            mint_a: Some(mint_a),
+            mint_b: Some(mint_b),
+            vault: Some(vault),
+            taker_ata_a: Some(ata(&taker.pubkey(), &mint_a)),
+            taker_ata_b: Some(taker_ata_b),
+            maker_ata_b: Some(ata(&maker.pubkey(), &mint_b)),
+            config: Some(config),
+            treasury: Some(treasury),
+            treasury_ata_b: Some(ata(&treasury, &mint_b)),
+            associated_token_program: spl_associated_token_account::id(),
+            token_program: spl_token_2022::ID,
+            system_program: system_program::ID,
+        })
+        .args(anchor_escrow::client::args::Take {
+            expected_receive: 500_000_000,
+            expected_deposit: 990_000_000,
+        })
+        .instruction()
+        .unwrap();
+    ctx.execute_instruction(take_ix, &[&taker])
+        .unwrap()
+        .assert_success();
+
+    // The taker's payment is grossed up so the maker gets all of `receive`.
+    assert_eq!(token_2022_balance(&ctx, &ata(&maker.pubkey(), &mint_b)), 500_000_000);
+    assert_eq!(token_2022_balance(&ctx, &taker_ata_b), 1_000_000_000 - 505_050_506);
+    // The vault's payout to the taker is charged the fee once more.
+    assert_eq!(token_2022_balance(&ctx, &ata(&taker.pubkey(), &mint_a)), 980_100_000);
+
+    // Non-transferable mints are rejected up front.
+    let soulbound = set_token_2022_mint(&mut ctx, &maker.pubkey(), Some(ExtensionType::NonTransferable));
+    let maker_ata = set_token_2022_ata(&mut ctx, &soulbound, &maker.pubkey(), 1_000_000_000, false);
+    let (ix, _, _) = make_ix(&mut ctx, 2, soulbound, maker_ata);
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_failure();
+}
+
+#[test]
+fn test_escrow_amend() {
+    let EscrowSetup {
+        mut ctx,
+        maker,
+        taker,
+        mint_a,
+        mint_b,
+        maker_ata_a,
+        taker_ata_b,
+        escrow: escrow_pda,
+        vault,
+        config,
+        treasury,
+    } = setup_escrow(11, Some(3_600), false);
+    let clock: Clock = ctx.svm.get_sysvar();
+
+    let amend_ix = |ctx: &mut AnchorLiteSVM, signer: &Keypair, receive: u64, deposit: u64, expiry: Option<i64>| {
+        ctx.program()
+            .accounts(anchor_escrow::client::accounts::Amend {
+                maker: signer.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a.pubkey()),
+                vault: Some(vault),
+                maker_ata_a: Some(get_associated_token_address(&signer.pubkey(), &mint_a.pubkey())),
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::Amend {
+                receive,
+                deposit,
+                expiry,
+            })
+            .instruction()
+            .unwrap()
+    };
+
+    // Only the maker can amend.
+    let ix = amend_ix(&mut ctx, &taker, 1, 1, None);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    // Withdraw part of the vault, reprice and push the expiry out.
+    let ix = amend_ix(&mut ctx, &maker, 200_000_000, 400_000_000, Some(clock.unix_timestamp + 7_200));
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_token_balance(&vault, 400_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_a, 600_000_000);
+
+    // Top it back up; the escrow keeps its address.
+    let ix = amend_ix(&mut ctx, &maker, 300_000_000, 900_000_000, None);
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_token_balance(&vault, 900_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_a, 100_000_000);
+
+    // An expiry in the past is rejected.
+    let ix = amend_ix(&mut ctx, &maker, 300_000_000, 900_000_000, Some(clock.unix_timestamp - 1));
+    ctx.execute_instruction(ix, &[&maker])
+        .unwrap()
+        .assert_failure();
+
+    // The taker pays the amended price for the amended deposit.
+    let maker_ata_b = get_associated_token_address(&maker.pubkey(), &mint_b.pubkey());
+    let taker_ata_a = get_associated_token_address(&taker.pubkey(), &mint_a.pubkey());
+    let take_ix = |ctx: &mut AnchorLiteSVM, expected_receive: u64, expected_deposit: u64| {
+        ctx.program()
+            .accounts(anchor_escrow::client::accounts::Take {
+                taker: taker.pubkey(),
+                maker: maker.pubkey(),
+                escrow: escrow_pda,
+                mint_a: Some(mint_a.pubkey()),
+                mint_b: Some(mint_b.pubkey()),
+                vault: Some(vault),
+                taker_ata_a: Some(taker_ata_a),
+                taker_ata_b: Some(taker_ata_b),
+                maker_ata_b: Some(maker_ata_b),
+                config: Some(config),
+                treasury: Some(treasury),
+                treasury_ata_b: Some(get_associated_token_address(&treasury, &mint_b.pubkey())),
+                associated_token_program: spl_associated_token_account::id(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            })
+            .args(anchor_escrow::client::args::Take {
+                expected_receive,
+                expected_deposit,
+            })
+            .instruction()
+            .unwrap()
+    };
+
+    // A take quoted before the last reprice fails instead of paying more.
+    let ix = take_ix(&mut ctx, 200_000_000, 400_000_000);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_failure();
+
+    let ix = take_ix(&mut ctx, 300_000_000, 900_000_000);
+    ctx.execute_instruction(ix, &[&taker])
+        .unwrap()
+        .assert_success();
+    ctx.svm.assert_token_balance(&taker_ata_a, 900_000_000);
+    ctx.svm.assert_token_balance(&maker_ata_b, 300_000_000);
+    ctx.svm.assert_token_balance(&taker_ata_b, 200_000_000);
+}